@@ -0,0 +1,80 @@
+use std::env;
+use std::io;
+use std::net::TcpListener;
+use std::ops::Range;
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+
+pub const LISTEN_PID_ENV: &str = "LISTEN_PID";
+pub const LISTEN_FDS_ENV: &str = "LISTEN_FDS";
+
+/// First file descriptor passed by systemd.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Socket inherited from systemd.
+pub(crate) enum Inherited {
+    Tcp(TcpListener),
+    Uds(UnixListener),
+}
+
+/// Takes the sockets passed by systemd with socket activation, as described
+/// in `sd_listen_fds(3)`. The env variables are removed, so they're not
+/// inherited by the child processes.
+pub(crate) fn inherited_listeners() -> io::Result<Vec<Inherited>> {
+    let fds = listen_fds(
+        env::var(LISTEN_PID_ENV).ok().as_deref(),
+        env::var(LISTEN_FDS_ENV).ok().as_deref(),
+        std::process::id(),
+    );
+    env::remove_var(LISTEN_PID_ENV);
+    env::remove_var(LISTEN_FDS_ENV);
+
+    fds.map(|fd| {
+        // The family of the socket is checked with the local address
+        let tcp_listener = unsafe { TcpListener::from_raw_fd(fd) };
+        if tcp_listener.local_addr().is_ok() {
+            return Ok(Inherited::Tcp(tcp_listener));
+        }
+        let fd = tcp_listener.into_raw_fd();
+        let uds_listener = unsafe { UnixListener::from_raw_fd(fd) };
+        match uds_listener.local_addr() {
+            Ok(_) => Ok(Inherited::Uds(uds_listener)),
+            Err(e) => {
+                // Not a socket we can serve, leave it open
+                let _ = uds_listener.into_raw_fd();
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("File descriptor {} is not a TCP or UDS socket: {}", fd, e),
+                ))
+            }
+        }
+    })
+    .collect()
+}
+
+/// File descriptors passed to the process `pid`.
+fn listen_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> Range<RawFd> {
+    let empty = LISTEN_FDS_START..LISTEN_FDS_START;
+    match listen_pid.and_then(|p| p.parse::<u32>().ok()) {
+        Some(listen_pid) if listen_pid == pid => {}
+        _ => return empty,
+    }
+    match listen_fds.and_then(|n| n.parse::<RawFd>().ok()) {
+        Some(n) if n > 0 => LISTEN_FDS_START..LISTEN_FDS_START + n,
+        _ => empty,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_fds() {
+        assert_eq!(listen_fds(Some("42"), Some("2"), 42), 3..5);
+        assert_eq!(listen_fds(Some("42"), Some("2"), 43).len(), 0);
+        assert_eq!(listen_fds(None, Some("2"), 42).len(), 0);
+        assert_eq!(listen_fds(Some("42"), Some("0"), 42).len(), 0);
+        assert_eq!(listen_fds(Some("42"), Some("many"), 42).len(), 0);
+    }
+}
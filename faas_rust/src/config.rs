@@ -0,0 +1,1026 @@
+use crate::dead_letter::DeadLetterQueue;
+use crate::dedup::{Deduplicator, MemoryStore, DEFAULT_CAPACITY};
+use crate::emitter::{Emitter, FileTarget};
+use crate::logging::LogFormat;
+use crate::payload::PayloadLimits;
+use crate::response_writer::ResponseEncoding;
+use crate::runtime::BindAddress;
+use crate::sink::{Sink, SinkEncoding, K_SINK_ENV};
+use crate::tls::TlsConfig;
+use crate::uds::{SocketMode, SocketPermissions};
+use crate::{health, metrics};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const CONFIG_FILE_ENV: &str = "FAAS_CONFIG";
+pub const PORT_ENV: &str = "PORT";
+pub const UNIX_DOMAIN_SOCKET_ENV: &str = "UNIX_DOMAIN_SOCKET";
+pub const HOST_ENV: &str = "FAAS_HOST";
+/// Comma separated listeners, e.g. `[::]:8080,unix:/run/function.sock`
+pub const LISTENERS_ENV: &str = "FAAS_LISTENERS";
+/// Octal file mode of the Unix Domain Sockets, e.g. `660`
+pub const UDS_MODE_ENV: &str = "FAAS_UDS_MODE";
+pub const UDS_OWNER_ENV: &str = "FAAS_UDS_OWNER";
+pub const UDS_GROUP_ENV: &str = "FAAS_UDS_GROUP";
+pub const SOCKET_ACTIVATION_ENV: &str = "FAAS_SOCKET_ACTIVATION";
+pub const H2C_ENV: &str = "FAAS_H2C";
+pub const TLS_CERT_ENV: &str = "FAAS_TLS_CERT";
+pub const TLS_KEY_ENV: &str = "FAAS_TLS_KEY";
+pub const TLS_CLIENT_CA_ENV: &str = "FAAS_TLS_CLIENT_CA";
+pub const LOG_ENV: &str = "FAAS_LOG";
+pub const LOG_FORMAT_ENV: &str = "FAAS_LOG_FORMAT";
+pub const LOG_FILTER_ENV: &str = "FAAS_LOG_FILTER";
+pub const WORKERS_ENV: &str = "FAAS_WORKERS";
+pub const SHUTDOWN_TIMEOUT_ENV: &str = "FAAS_SHUTDOWN_TIMEOUT";
+pub const PAYLOAD_LIMIT_ENV: &str = "FAAS_PAYLOAD_LIMIT";
+pub const BINARY_PAYLOAD_LIMIT_ENV: &str = "FAAS_BINARY_PAYLOAD_LIMIT";
+pub const STRUCTURED_PAYLOAD_LIMIT_ENV: &str = "FAAS_STRUCTURED_PAYLOAD_LIMIT";
+pub const BATCH_PAYLOAD_LIMIT_ENV: &str = "FAAS_BATCH_PAYLOAD_LIMIT";
+pub const INVOCATION_TIMEOUT_ENV: &str = "FAAS_INVOCATION_TIMEOUT";
+pub const CONCURRENCY_LIMIT_ENV: &str = "FAAS_CONCURRENCY_LIMIT";
+/// Comma separated `<event type>=<limit>` pairs
+pub const EVENT_TYPE_CONCURRENCY_LIMITS_ENV: &str = "FAAS_EVENT_TYPE_CONCURRENCY_LIMITS";
+pub const CONCURRENCY_QUEUE_SIZE_ENV: &str = "FAAS_CONCURRENCY_QUEUE_SIZE";
+pub const CONCURRENCY_QUEUE_TIMEOUT_ENV: &str = "FAAS_CONCURRENCY_QUEUE_TIMEOUT";
+pub const RETRY_AFTER_ENV: &str = "FAAS_RETRY_AFTER";
+pub const METRICS_ENV: &str = "FAAS_METRICS";
+pub const METRICS_PATH_ENV: &str = "FAAS_METRICS_PATH";
+/// Comma separated event types
+pub const METRICS_EVENT_TYPES_ENV: &str = "FAAS_METRICS_EVENT_TYPES";
+pub const TRACING_ENV: &str = "FAAS_TRACING";
+pub const RESPONSE_ENCODING_ENV: &str = "FAAS_RESPONSE_ENCODING";
+pub const ERROR_EVENTS_ENV: &str = "FAAS_ERROR_EVENTS";
+pub const ERROR_EVENT_TYPE_ENV: &str = "FAAS_ERROR_EVENT_TYPE";
+pub const ERROR_EVENT_SOURCE_ENV: &str = "FAAS_ERROR_EVENT_SOURCE";
+pub const SINK_ENCODING_ENV: &str = "FAAS_SINK_ENCODING";
+pub const SINK_RETRIES_ENV: &str = "FAAS_SINK_RETRIES";
+pub const SINK_BACKOFF_ENV: &str = "FAAS_SINK_BACKOFF";
+/// Comma separated `<name>=<url>` pairs
+pub const EMITTER_TARGETS_ENV: &str = "FAAS_EMITTER_TARGETS";
+pub const DEAD_LETTER_SINK_ENV: &str = "FAAS_DEAD_LETTER_SINK";
+pub const DEDUP_ENV: &str = "FAAS_DEDUP";
+pub const DEDUP_TTL_ENV: &str = "FAAS_DEDUP_TTL";
+pub const DEDUP_CAPACITY_ENV: &str = "FAAS_DEDUP_CAPACITY";
+pub const LIVENESS_PATH_ENV: &str = "FAAS_LIVENESS_PATH";
+pub const READINESS_PATH_ENV: &str = "FAAS_READINESS_PATH";
+
+const DEFAULT_CONFIG_FILE: &str = "func.yaml";
+
+/// Configuration of the runtime.
+///
+/// It's loaded from the `runtimeConfig` section of the config file, if any,
+/// and then overridden by the environment variables. The config file is the
+/// one pointed by `FAAS_CONFIG` or, if it's not set, the `func.yaml` in the
+/// working directory. Both TOML and YAML files are supported.
+///
+/// The functions can access it declaring an argument of type `&RuntimeConfig`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// Address of the TCP listener, e.g. `::` for IPv6/dual-stack
+    pub host: IpAddr,
+    /// Port of the TCP listener, 0 to let the OS assign it
+    pub port: u16,
+    pub unix_domain_socket: Option<String>,
+    /// Listeners replacing the `host`, `port` and `unix_domain_socket` ones
+    pub listeners: Vec<BindAddress>,
+    /// Octal file mode of the Unix Domain Sockets, e.g. `"660"`
+    pub uds_mode: Option<SocketMode>,
+    /// Numeric user id owning the Unix Domain Sockets
+    pub uds_owner: Option<u32>,
+    /// Numeric group id owning the Unix Domain Sockets
+    pub uds_group: Option<u32>,
+    /// Serve on the sockets passed by systemd, if any, instead of the
+    /// listeners
+    pub socket_activation: bool,
+    /// Accept HTTP/2 cleartext connections with prior knowledge on the TCP
+    /// listeners
+    pub h2c: bool,
+    /// Path of the PEM certificate, enables HTTPS on the TCP listeners
+    pub tls_cert: Option<String>,
+    /// Path of the PEM private key
+    pub tls_key: Option<String>,
+    /// Path of the PEM CA certificates of the clients, enables mTLS
+    pub tls_client_ca: Option<String>,
+    /// Enable the logging, including the access log. Disabled by default
+    pub log: bool,
+    pub log_format: LogFormat,
+    /// Log filter, in the `env_logger` format. Enables the logging
+    pub log_filter: Option<String>,
+    pub workers: Option<usize>,
+    /// Seconds
+    pub shutdown_timeout: Option<u64>,
+    /// Bytes, for every encoding
+    pub payload_limit: Option<usize>,
+    /// Bytes, overrides `payload_limit` for binary events
+    pub binary_payload_limit: Option<usize>,
+    /// Bytes, overrides `payload_limit` for structured events
+    pub structured_payload_limit: Option<usize>,
+    /// Bytes, overrides `payload_limit` for batches of events
+    pub batch_payload_limit: Option<usize>,
+    /// Seconds
+    pub invocation_timeout: Option<u64>,
+    /// Maximum invocations in progress
+    pub concurrency_limit: Option<usize>,
+    /// Maximum invocations in progress by event type
+    pub event_type_concurrency_limits: HashMap<String, usize>,
+    /// Invocations waiting when a concurrency limit is reached
+    pub concurrency_queue_size: usize,
+    /// Seconds
+    pub concurrency_queue_timeout: Option<u64>,
+    /// Seconds in the `Retry-After` header of the rejected invocations
+    pub retry_after: Option<u64>,
+    pub liveness_path: String,
+    pub readiness_path: String,
+    /// Expose the Prometheus metrics endpoint
+    pub metrics: bool,
+    pub metrics_path: String,
+    /// Event types with their own label in the metrics, the others are
+    /// counted as `other`
+    pub metrics_event_types: Vec<String>,
+    /// Log the invocation spans as JSON, at info level
+    pub tracing: bool,
+    /// Encoding of the responses when the caller doesn't accept a specific
+    /// one, defaults to the encoding of the request
+    pub response_encoding: Option<ResponseEncoding>,
+    /// Reply to the failed invocations with error events
+    pub error_events: bool,
+    pub error_event_type: Option<String>,
+    pub error_event_source: Option<String>,
+    /// URL receiving the events returned by the functions, e.g. the
+    /// `K_SINK` injected by Knative
+    pub sink: Option<String>,
+    pub sink_encoding: SinkEncoding,
+    /// Delivery attempts after the first failed one
+    pub sink_retries: Option<u32>,
+    /// Milliseconds before the first retry, doubled at every retry
+    pub sink_backoff: Option<u64>,
+    /// Targets of the `Emitter` by name: HTTP URLs, sharing the delivery
+    /// settings of the sink, or `file:<path>`
+    pub emitter_targets: HashMap<String, String>,
+    /// Where the events which couldn't be processed or delivered are
+    /// recorded: an HTTP URL or `file:<path>`
+    pub dead_letter_sink: Option<String>,
+    /// Invoke the functions once for every event source and id
+    pub dedup: bool,
+    /// Seconds the responses are replayed to the duplicates
+    pub dedup_ttl: Option<u64>,
+    /// Maximum cached responses
+    pub dedup_capacity: Option<usize>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        RuntimeConfig {
+            host: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8080,
+            unix_domain_socket: None,
+            listeners: Vec::new(),
+            uds_mode: None,
+            uds_owner: None,
+            uds_group: None,
+            socket_activation: true,
+            h2c: false,
+            tls_cert: None,
+            tls_key: None,
+            tls_client_ca: None,
+            log: false,
+            log_format: LogFormat::Text,
+            log_filter: None,
+            workers: None,
+            shutdown_timeout: None,
+            payload_limit: None,
+            binary_payload_limit: None,
+            structured_payload_limit: None,
+            batch_payload_limit: None,
+            invocation_timeout: None,
+            concurrency_limit: None,
+            event_type_concurrency_limits: HashMap::new(),
+            concurrency_queue_size: 0,
+            concurrency_queue_timeout: None,
+            retry_after: None,
+            liveness_path: String::from(health::DEFAULT_LIVENESS_PATH),
+            readiness_path: String::from(health::DEFAULT_READINESS_PATH),
+            metrics: false,
+            metrics_path: String::from(metrics::DEFAULT_METRICS_PATH),
+            metrics_event_types: Vec::new(),
+            tracing: false,
+            response_encoding: None,
+            error_events: false,
+            error_event_type: None,
+            error_event_source: None,
+            sink: None,
+            sink_encoding: SinkEncoding::Binary,
+            sink_retries: None,
+            sink_backoff: None,
+            emitter_targets: HashMap::new(),
+            dead_letter_sink: None,
+            dedup: false,
+            dedup_ttl: None,
+            dedup_capacity: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    File(PathBuf, String),
+    Env(&'static str, String, String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Cannot read {}: {}", path.display(), e),
+            ConfigError::File(path, e) => write!(f, "Invalid config {}: {}", path.display(), e),
+            ConfigError::Env(name, value, e) => {
+                write!(f, "Invalid value '{}' for env {}: {}", value, name, e)
+            }
+            ConfigError::Invalid(e) => write!(f, "Invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for std::io::Error {
+    fn from(e: ConfigError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+    }
+}
+
+/// Config file, e.g. the `func.yaml` of the function, where the `runtime`
+/// key already holds the language of the function.
+#[derive(Deserialize, Default)]
+struct ConfigFile {
+    #[serde(default, rename = "runtimeConfig")]
+    runtime_config: RuntimeConfig,
+}
+
+impl RuntimeConfig {
+    /// Loads the config from the config file and the environment variables.
+    pub fn load() -> Result<RuntimeConfig, ConfigError> {
+        let file = match env::var(CONFIG_FILE_ENV) {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Some(PathBuf::from(DEFAULT_CONFIG_FILE))
+            }
+            Err(_) => None,
+        };
+
+        let mut config = match file {
+            Some(path) => RuntimeConfig::from_file(&path)?,
+            None => RuntimeConfig::default(),
+        };
+        config.apply_env(|name| env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<RuntimeConfig, ConfigError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        let file: ConfigFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content).map_err(|e| e.to_string()),
+            _ => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| ConfigError::File(path.to_owned(), e))?;
+        Ok(file.runtime_config)
+    }
+
+    /// Addresses the runtime listens on.
+    pub fn bind_addresses(&self) -> Vec<BindAddress> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+        match &self.unix_domain_socket {
+            Some(uds) => vec![BindAddress::Uds(uds.clone())],
+            None => vec![BindAddress::Tcp(SocketAddr::new(self.host, self.port))],
+        }
+    }
+
+    /// Mode and owner of the Unix Domain Sockets.
+    pub fn socket_permissions(&self) -> SocketPermissions {
+        SocketPermissions {
+            mode: self.uds_mode,
+            owner: self.uds_owner,
+            group: self.uds_group,
+        }
+    }
+
+    /// Sink of the output events, if any.
+    pub fn sink(&self) -> Option<Sink> {
+        self.sink.as_ref().map(|url| self.sink_to(url))
+    }
+
+    /// Emitter with the configured targets, if any.
+    pub fn emitter(&self) -> Option<Emitter> {
+        if self.emitter_targets.is_empty() {
+            return None;
+        }
+        let emitter = self
+            .emitter_targets
+            .iter()
+            .fold(Emitter::new(), |emitter, (name, url)| {
+                match url.strip_prefix("file:") {
+                    Some(path) => emitter.target(name.clone(), FileTarget::new(path)),
+                    None => emitter.target(name.clone(), self.sink_to(url)),
+                }
+            });
+        Some(emitter)
+    }
+
+    /// Dead letter queue, if enabled.
+    pub fn dead_letters(&self) -> Option<DeadLetterQueue> {
+        let url = self.dead_letter_sink.as_ref()?;
+        Some(match url.strip_prefix("file:") {
+            Some(path) => DeadLetterQueue::new(FileTarget::new(path)),
+            None => DeadLetterQueue::new(self.sink_to(url)),
+        })
+    }
+
+    /// In-memory deduplicator, if enabled.
+    pub fn deduplicator(&self) -> Option<Deduplicator> {
+        if !self.dedup {
+            return None;
+        }
+        let store = MemoryStore::new(self.dedup_capacity.unwrap_or(DEFAULT_CAPACITY));
+        let mut deduplicator = Deduplicator::new(store);
+        if let Some(ttl) = self.dedup_ttl {
+            deduplicator = deduplicator.ttl(Duration::from_secs(ttl));
+        }
+        Some(deduplicator)
+    }
+
+    fn sink_to(&self, url: &str) -> Sink {
+        let mut sink = Sink::new(url).encoding(self.sink_encoding);
+        if let Some(retries) = self.sink_retries {
+            sink = sink.retries(retries);
+        }
+        if let Some(backoff) = self.sink_backoff {
+            sink = sink.backoff(Duration::from_millis(backoff));
+        }
+        sink
+    }
+
+    /// TLS configuration, if HTTPS is enabled.
+    pub fn tls(&self) -> Option<TlsConfig> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                let tls = TlsConfig::new(cert, key);
+                Some(match &self.tls_client_ca {
+                    Some(client_ca) => tls.client_ca(client_ca),
+                    None => tls,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Maximum size of the request payloads, by encoding.
+    pub fn payload_limits(&self) -> PayloadLimits {
+        let default = self
+            .payload_limit
+            .map(PayloadLimits::all)
+            .unwrap_or_default();
+        PayloadLimits {
+            binary: self.binary_payload_limit.unwrap_or(default.binary),
+            structured: self.structured_payload_limit.unwrap_or(default.structured),
+            batch: self.batch_payload_limit.unwrap_or(default.batch),
+        }
+    }
+
+    fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) -> Result<(), ConfigError> {
+        if let Some(host) = parse_env(&var, HOST_ENV)? {
+            self.host = host;
+        }
+        if let Some(port) = parse_env(&var, PORT_ENV)? {
+            self.port = port;
+        }
+        if let Some(uds) = var(UNIX_DOMAIN_SOCKET_ENV) {
+            self.unix_domain_socket = Some(uds);
+        }
+        if let Some(value) = var(LISTENERS_ENV) {
+            self.listeners = value
+                .split(',')
+                .filter(|l| !l.trim().is_empty())
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|e| ConfigError::Env(LISTENERS_ENV, value.clone(), e))?;
+        }
+        if let Some(uds_mode) = parse_env(&var, UDS_MODE_ENV)? {
+            self.uds_mode = Some(uds_mode);
+        }
+        if let Some(uds_owner) = parse_env(&var, UDS_OWNER_ENV)? {
+            self.uds_owner = Some(uds_owner);
+        }
+        if let Some(uds_group) = parse_env(&var, UDS_GROUP_ENV)? {
+            self.uds_group = Some(uds_group);
+        }
+        if let Some(socket_activation) = parse_env(&var, SOCKET_ACTIVATION_ENV)? {
+            self.socket_activation = socket_activation;
+        }
+        if let Some(h2c) = parse_env(&var, H2C_ENV)? {
+            self.h2c = h2c;
+        }
+        if let Some(tls_cert) = var(TLS_CERT_ENV) {
+            self.tls_cert = Some(tls_cert);
+        }
+        if let Some(tls_key) = var(TLS_KEY_ENV) {
+            self.tls_key = Some(tls_key);
+        }
+        if let Some(tls_client_ca) = var(TLS_CLIENT_CA_ENV) {
+            self.tls_client_ca = Some(tls_client_ca);
+        }
+        if let Some(log) = parse_env(&var, LOG_ENV)? {
+            self.log = log;
+        }
+        if let Some(log_format) = parse_env(&var, LOG_FORMAT_ENV)? {
+            self.log_format = log_format;
+        }
+        if let Some(log_filter) = var(LOG_FILTER_ENV) {
+            self.log_filter = Some(log_filter);
+        }
+        if let Some(workers) = parse_env(&var, WORKERS_ENV)? {
+            self.workers = Some(workers);
+        }
+        if let Some(shutdown_timeout) = parse_env(&var, SHUTDOWN_TIMEOUT_ENV)? {
+            self.shutdown_timeout = Some(shutdown_timeout);
+        }
+        if let Some(payload_limit) = parse_env(&var, PAYLOAD_LIMIT_ENV)? {
+            self.payload_limit = Some(payload_limit);
+        }
+        if let Some(limit) = parse_env(&var, BINARY_PAYLOAD_LIMIT_ENV)? {
+            self.binary_payload_limit = Some(limit);
+        }
+        if let Some(limit) = parse_env(&var, STRUCTURED_PAYLOAD_LIMIT_ENV)? {
+            self.structured_payload_limit = Some(limit);
+        }
+        if let Some(limit) = parse_env(&var, BATCH_PAYLOAD_LIMIT_ENV)? {
+            self.batch_payload_limit = Some(limit);
+        }
+        if let Some(invocation_timeout) = parse_env(&var, INVOCATION_TIMEOUT_ENV)? {
+            self.invocation_timeout = Some(invocation_timeout);
+        }
+        if let Some(concurrency_limit) = parse_env(&var, CONCURRENCY_LIMIT_ENV)? {
+            self.concurrency_limit = Some(concurrency_limit);
+        }
+        if let Some(value) = var(EVENT_TYPE_CONCURRENCY_LIMITS_ENV) {
+            self.event_type_concurrency_limits = parse_pairs(&value, "<event type>=<limit>")
+                .map_err(|e| {
+                    ConfigError::Env(EVENT_TYPE_CONCURRENCY_LIMITS_ENV, value.clone(), e)
+                })?;
+        }
+        if let Some(queue_size) = parse_env(&var, CONCURRENCY_QUEUE_SIZE_ENV)? {
+            self.concurrency_queue_size = queue_size;
+        }
+        if let Some(queue_timeout) = parse_env(&var, CONCURRENCY_QUEUE_TIMEOUT_ENV)? {
+            self.concurrency_queue_timeout = Some(queue_timeout);
+        }
+        if let Some(retry_after) = parse_env(&var, RETRY_AFTER_ENV)? {
+            self.retry_after = Some(retry_after);
+        }
+        if let Some(liveness_path) = var(LIVENESS_PATH_ENV) {
+            self.liveness_path = liveness_path;
+        }
+        if let Some(readiness_path) = var(READINESS_PATH_ENV) {
+            self.readiness_path = readiness_path;
+        }
+        if let Some(metrics) = parse_env(&var, METRICS_ENV)? {
+            self.metrics = metrics;
+        }
+        if let Some(metrics_path) = var(METRICS_PATH_ENV) {
+            self.metrics_path = metrics_path;
+        }
+        if let Some(value) = var(METRICS_EVENT_TYPES_ENV) {
+            self.metrics_event_types = value
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        if let Some(tracing) = parse_env(&var, TRACING_ENV)? {
+            self.tracing = tracing;
+        }
+        if let Some(response_encoding) = parse_env(&var, RESPONSE_ENCODING_ENV)? {
+            self.response_encoding = Some(response_encoding);
+        }
+        if let Some(error_events) = parse_env(&var, ERROR_EVENTS_ENV)? {
+            self.error_events = error_events;
+        }
+        if let Some(error_event_type) = var(ERROR_EVENT_TYPE_ENV) {
+            self.error_event_type = Some(error_event_type);
+        }
+        if let Some(error_event_source) = var(ERROR_EVENT_SOURCE_ENV) {
+            self.error_event_source = Some(error_event_source);
+        }
+        if let Some(sink) = var(K_SINK_ENV) {
+            self.sink = Some(sink);
+        }
+        if let Some(sink_encoding) = parse_env(&var, SINK_ENCODING_ENV)? {
+            self.sink_encoding = sink_encoding;
+        }
+        if let Some(sink_retries) = parse_env(&var, SINK_RETRIES_ENV)? {
+            self.sink_retries = Some(sink_retries);
+        }
+        if let Some(sink_backoff) = parse_env(&var, SINK_BACKOFF_ENV)? {
+            self.sink_backoff = Some(sink_backoff);
+        }
+        if let Some(value) = var(EMITTER_TARGETS_ENV) {
+            self.emitter_targets = parse_pairs(&value, "<name>=<url>")
+                .map_err(|e| ConfigError::Env(EMITTER_TARGETS_ENV, value.clone(), e))?;
+        }
+        if let Some(dead_letter_sink) = var(DEAD_LETTER_SINK_ENV) {
+            self.dead_letter_sink = Some(dead_letter_sink);
+        }
+        if let Some(dedup) = parse_env(&var, DEDUP_ENV)? {
+            self.dedup = dedup;
+        }
+        if let Some(dedup_ttl) = parse_env(&var, DEDUP_TTL_ENV)? {
+            self.dedup_ttl = Some(dedup_ttl);
+        }
+        if let Some(dedup_capacity) = parse_env(&var, DEDUP_CAPACITY_ENV)? {
+            self.dedup_capacity = Some(dedup_capacity);
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.workers == Some(0) {
+            return Err(ConfigError::Invalid(String::from(
+                "workers should be greater than 0",
+            )));
+        }
+        if self.invocation_timeout == Some(0) {
+            return Err(ConfigError::Invalid(String::from(
+                "invocation_timeout should be greater than 0",
+            )));
+        }
+        if self.concurrency_limit == Some(0)
+            || self.event_type_concurrency_limits.values().any(|l| *l == 0)
+        {
+            return Err(ConfigError::Invalid(String::from(
+                "concurrency limits should be greater than 0",
+            )));
+        }
+        if self.dedup_ttl == Some(0) || self.dedup_capacity == Some(0) {
+            return Err(ConfigError::Invalid(String::from(
+                "dedup_ttl and dedup_capacity should be greater than 0",
+            )));
+        }
+        let limits = self.payload_limits();
+        if self.payload_limit == Some(0)
+            || limits.binary == 0
+            || limits.structured == 0
+            || limits.batch == 0
+        {
+            return Err(ConfigError::Invalid(String::from(
+                "payload limits should be greater than 0",
+            )));
+        }
+        for path in &[
+            &self.liveness_path,
+            &self.readiness_path,
+            &self.metrics_path,
+        ] {
+            if !path.starts_with('/') {
+                return Err(ConfigError::Invalid(format!(
+                    "path {} should start with /",
+                    path
+                )));
+            }
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err(ConfigError::Invalid(String::from(
+                "tls_cert and tls_key should be both provided",
+            )));
+        }
+        if self.tls_client_ca.is_some() && self.tls_cert.is_none() {
+            return Err(ConfigError::Invalid(String::from(
+                "tls_client_ca requires tls_cert and tls_key",
+            )));
+        }
+        if self.h2c && self.tls_cert.is_some() {
+            return Err(ConfigError::Invalid(String::from(
+                "h2c is not supported with TLS, where HTTP/2 is negotiated through ALPN",
+            )));
+        }
+        if let Some(sink) = &self.sink {
+            if !is_http_url(sink) {
+                return Err(ConfigError::Invalid(format!(
+                    "sink {} should be an http or https URL",
+                    sink
+                )));
+            }
+        }
+        if let Some(url) = &self.dead_letter_sink {
+            if !is_http_url(url) && !url.starts_with("file:") {
+                return Err(ConfigError::Invalid(format!(
+                    "dead_letter_sink {} should be an http or https URL or file:<path>",
+                    url
+                )));
+            }
+        }
+        for (name, url) in &self.emitter_targets {
+            if !is_http_url(url) && !url.starts_with("file:") {
+                return Err(ConfigError::Invalid(format!(
+                    "emitter target {} should be an http or https URL or file:<path>",
+                    name
+                )));
+            }
+        }
+        if let Some(uds) = &self.unix_domain_socket {
+            if uds.is_empty() {
+                return Err(ConfigError::Invalid(String::from(
+                    "unix_domain_socket should not be empty",
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_env<T, F>(var: &F, name: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+    F: Fn(&str) -> Option<String>,
+{
+    match var(name) {
+        Some(value) => value
+            .parse::<T>()
+            .map(Some)
+            .map_err(|e| ConfigError::Env(name, value, e.to_string())),
+        None => Ok(None),
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Parses comma separated `<key>=<value>` pairs.
+fn parse_pairs<T>(value: &str, expecting: &str) -> Result<HashMap<String, T>, String>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    value
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => value
+                    .trim()
+                    .parse::<T>()
+                    .map(|value| (key.trim().to_string(), value))
+                    .map_err(|e| e.to_string()),
+                _ => Err(format!("expecting {}, found {}", expecting, pair)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn vars(v: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let map: HashMap<String, String> = v
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| map.get(name).cloned()
+    }
+
+    #[test]
+    fn test_parse_yaml_ignoring_other_sections() {
+        let file: ConfigFile = serde_yaml::from_str(
+            "name: fold\nruntimeConfig:\n  port: 9090\n  workers: 2\n  log: true\n",
+        )
+        .unwrap();
+
+        assert_eq!(file.runtime_config.port, 9090);
+        assert_eq!(file.runtime_config.workers, Some(2));
+        assert!(file.runtime_config.log);
+        assert_eq!(file.runtime_config.unix_domain_socket, None);
+    }
+
+    #[test]
+    fn test_load_func_yaml() {
+        let func_yaml = "\
+specVersion: 0.35.0
+name: orders
+runtime: rust
+registry: quay.io/boson
+image: quay.io/boson/orders:latest
+created: 2026-10-19T10:00:00Z
+build:
+  builder: pack
+run:
+  envs:
+  - name: FAAS_LOG
+    value: 'true'
+deploy:
+  namespace: default
+";
+        let path = std::env::temp_dir().join(format!("func-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, func_yaml).unwrap();
+        let config = RuntimeConfig::from_file(&path);
+        std::fs::write(
+            &path,
+            format!("{}runtimeConfig:\n  port: 9090\n", func_yaml),
+        )
+        .unwrap();
+        let configured = RuntimeConfig::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.unwrap(), RuntimeConfig::default());
+        assert_eq!(configured.unwrap().port, 9090);
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let file: ConfigFile =
+            toml::from_str("[runtimeConfig]\nunix_domain_socket = \"/tmp/fn.sock\"\n").unwrap();
+
+        assert_eq!(file.runtime_config.port, 8080);
+        assert_eq!(
+            file.runtime_config.unix_domain_socket,
+            Some(String::from("/tmp/fn.sock"))
+        );
+    }
+
+    #[test]
+    fn test_unknown_field_is_rejected() {
+        assert!(serde_yaml::from_str::<ConfigFile>("runtimeConfig:\n  prot: 9090\n").is_err());
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let mut config = RuntimeConfig {
+            port: 9090,
+            ..RuntimeConfig::default()
+        };
+        config
+            .apply_env(vars(&[(PORT_ENV, "7070"), (LOG_ENV, "true")]))
+            .unwrap();
+
+        assert_eq!(config.port, 7070);
+        assert!(config.log);
+    }
+
+    #[test]
+    fn test_invalid_env_is_reported() {
+        let mut config = RuntimeConfig::default();
+        let err = config.apply_env(vars(&[(PORT_ENV, "http")])).unwrap_err();
+
+        match err {
+            ConfigError::Env(name, value, _) => {
+                assert_eq!(name, PORT_ENV);
+                assert_eq!(value, "http");
+            }
+            e => panic!("Unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn test_event_type_concurrency_limits_env() {
+        let mut config = RuntimeConfig::default();
+        config
+            .apply_env(vars(&[(
+                EVENT_TYPE_CONCURRENCY_LIMITS_ENV,
+                "dev.knative.order=10, dev.knative.payment=2",
+            )]))
+            .unwrap();
+
+        assert_eq!(
+            config
+                .event_type_concurrency_limits
+                .get("dev.knative.order"),
+            Some(&10)
+        );
+        assert_eq!(
+            config
+                .event_type_concurrency_limits
+                .get("dev.knative.payment"),
+            Some(&2)
+        );
+        assert!(config
+            .apply_env(vars(&[(
+                EVENT_TYPE_CONCURRENCY_LIMITS_ENV,
+                "dev.knative.order"
+            )]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_payload_limits_by_encoding() {
+        let config = RuntimeConfig {
+            payload_limit: Some(1024),
+            batch_payload_limit: Some(4096),
+            ..RuntimeConfig::default()
+        };
+
+        let limits = config.payload_limits();
+        assert_eq!(limits.binary, 1024);
+        assert_eq!(limits.structured, 1024);
+        assert_eq!(limits.batch, 4096);
+    }
+
+    #[test]
+    fn test_listeners() {
+        let file: ConfigFile = serde_yaml::from_str(
+            "runtimeConfig:\n  listeners: ['[::]:0', 'unix:/run/function.sock']\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            file.runtime_config.bind_addresses(),
+            vec![
+                BindAddress::Tcp("[::]:0".parse().unwrap()),
+                BindAddress::Uds(String::from("/run/function.sock"))
+            ]
+        );
+        assert!(
+            serde_yaml::from_str::<ConfigFile>("runtimeConfig:\n  listeners: ['localhost']\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_socket_permissions() {
+        let file: ConfigFile = serde_yaml::from_str("runtimeConfig:\n  uds_mode: '660'\n").unwrap();
+        let mut config = file.runtime_config;
+        config
+            .apply_env(vars(&[(UDS_OWNER_ENV, "1000"), (UDS_GROUP_ENV, "1000")]))
+            .unwrap();
+
+        assert_eq!(
+            config.socket_permissions(),
+            SocketPermissions {
+                mode: Some(SocketMode(0o660)),
+                owner: Some(1000),
+                group: Some(1000),
+            }
+        );
+        assert!(config.apply_env(vars(&[(UDS_MODE_ENV, "rw")])).is_err());
+    }
+
+    #[test]
+    fn test_host_override() {
+        let mut config = RuntimeConfig::default();
+        config
+            .apply_env(vars(&[(HOST_ENV, "::1"), (PORT_ENV, "9090")]))
+            .unwrap();
+
+        assert_eq!(
+            config.bind_addresses(),
+            vec![BindAddress::Tcp("[::1]:9090".parse().unwrap())]
+        );
+    }
+
+    #[test]
+    fn test_zero_workers_is_invalid() {
+        let config = RuntimeConfig {
+            workers: Some(0),
+            ..RuntimeConfig::default()
+        };
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_k_sink() {
+        let mut config = RuntimeConfig::default();
+        config
+            .apply_env(vars(&[
+                (K_SINK_ENV, "http://broker-ingress.knative-eventing/default"),
+                (SINK_ENCODING_ENV, "structured"),
+                (SINK_RETRIES_ENV, "5"),
+            ]))
+            .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.sink(),
+            Some(
+                Sink::new("http://broker-ingress.knative-eventing/default")
+                    .encoding(SinkEncoding::Structured)
+                    .retries(5)
+            )
+        );
+
+        config.sink = Some(String::from("broker-ingress"));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_emitter_targets_env() {
+        let mut config = RuntimeConfig::default();
+        assert!(config.emitter().is_none());
+        config
+            .apply_env(vars(&[(
+                EMITTER_TARGETS_ENV,
+                "audit=file:/var/log/audit.ndjson, orders=http://orders.default?v=1",
+            )]))
+            .unwrap();
+
+        assert_eq!(
+            config.emitter_targets.get("orders").map(String::as_str),
+            Some("http://orders.default?v=1")
+        );
+        assert!(config.validate().is_ok());
+        assert!(config.emitter().is_some());
+
+        config
+            .emitter_targets
+            .insert(String::from("ftp"), String::from("ftp://files"));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_metrics_event_types_env() {
+        let mut config = RuntimeConfig::default();
+        config
+            .apply_env(vars(&[(
+                METRICS_EVENT_TYPES_ENV,
+                "dev.knative.order, dev.knative.payment,",
+            )]))
+            .unwrap();
+
+        assert_eq!(
+            config.metrics_event_types,
+            vec!["dev.knative.order", "dev.knative.payment"]
+        );
+    }
+
+    #[test]
+    fn test_dead_letter_sink() {
+        let mut config = RuntimeConfig::default();
+        assert!(config.dead_letters().is_none());
+        config
+            .apply_env(vars(&[(
+                DEAD_LETTER_SINK_ENV,
+                "file:/var/lib/function/dead.ndjson",
+            )]))
+            .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert!(config.dead_letters().is_some());
+
+        config.dead_letter_sink = Some(String::from("/var/lib/function/dead.ndjson"));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_dedup() {
+        let mut config = RuntimeConfig::default();
+        assert!(config.deduplicator().is_none());
+        config
+            .apply_env(vars(&[
+                (DEDUP_ENV, "true"),
+                (DEDUP_TTL_ENV, "3600"),
+                (DEDUP_CAPACITY_ENV, "500"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.dedup_ttl, Some(3600));
+        assert_eq!(config.dedup_capacity, Some(500));
+        assert!(config.validate().is_ok());
+        assert!(config.deduplicator().is_some());
+
+        config.dedup_capacity = Some(0);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_response_encoding() {
+        let file: ConfigFile =
+            serde_yaml::from_str("runtimeConfig:\n  response_encoding: protobuf-batch\n").unwrap();
+        assert_eq!(
+            file.runtime_config.response_encoding,
+            Some(ResponseEncoding::ProtobufBatch)
+        );
+
+        let mut config = RuntimeConfig::default();
+        config
+            .apply_env(vars(&[(RESPONSE_ENCODING_ENV, "structured")]))
+            .unwrap();
+        assert_eq!(config.response_encoding, Some(ResponseEncoding::Structured));
+        assert!(config
+            .apply_env(vars(&[(RESPONSE_ENCODING_ENV, "xml")]))
+            .is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use actix_web::HttpRequest;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+/// Header with the deadline of the invocation, as RFC 3339 timestamp.
+pub const DEADLINE_HEADER: &str = "faas-deadline";
+
+/// Context of the invocation in progress. Functions can access it declaring
+/// an argument of type `Context`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Context {
+    deadline: Option<DateTime<Utc>>,
+}
+
+impl Context {
+    /// Context of an invocation which must complete within `timeout`, if
+    /// any, and before the deadline of the request, if any.
+    pub(crate) fn new(req: &HttpRequest, timeout: Option<Duration>) -> Context {
+        let now = Utc::now();
+        let timeout_deadline = timeout
+            .and_then(|t| chrono::Duration::from_std(t).ok())
+            .map(|t| now + t);
+        let request_deadline = req
+            .headers()
+            .get(DEADLINE_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_deadline);
+
+        Context {
+            deadline: match (timeout_deadline, request_deadline) {
+                (Some(t), Some(r)) => Some(t.min(r)),
+                (t, r) => t.or(r),
+            },
+        }
+    }
+
+    /// Instant when the invocation is cancelled, to be propagated to the
+    /// downstream calls.
+    pub fn deadline(&self) -> Option<DateTime<Utc>> {
+        self.deadline
+    }
+
+    /// Time left to complete the invocation, if it has a deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| {
+            (deadline - Utc::now())
+                .to_std()
+                .unwrap_or_else(|_| Duration::from_secs(0))
+        })
+    }
+}
+
+fn parse_deadline(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// Maximum duration of the invocations, registered as app data.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct InvocationTimeout(pub Duration);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_earliest_deadline_wins() {
+        let deadline = Utc::now() + chrono::Duration::seconds(5);
+        let req = TestRequest::default()
+            .header(DEADLINE_HEADER, deadline.to_rfc3339())
+            .to_http_request();
+
+        let ctx = Context::new(&req, Some(Duration::from_secs(60)));
+        assert_eq!(ctx.deadline(), Some(deadline));
+        assert!(ctx.remaining().unwrap() <= Duration::from_secs(5));
+
+        let ctx = Context::new(&req, Some(Duration::from_secs(1)));
+        assert!(ctx.deadline().unwrap() < deadline);
+    }
+
+    #[test]
+    fn test_no_deadline() {
+        let req = TestRequest::default()
+            .header(DEADLINE_HEADER, "tomorrow")
+            .to_http_request();
+
+        assert_eq!(Context::new(&req, None), Context::default());
+        assert_eq!(Context::default().remaining(), None);
+    }
+}
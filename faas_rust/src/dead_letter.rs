@@ -0,0 +1,186 @@
+use crate::emitter::Target;
+use cloudevent::Event;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Extension of the dead-lettered events with the error.
+pub const ERROR_EXTENSION: &str = "deadlettererror";
+/// Extension of the dead-lettered events with the delivery attempts.
+pub const ATTEMPTS_EXTENSION: &str = "deadletterattempts";
+/// Extension of the dead-lettered events with the failed stage.
+pub const STAGE_EXTENSION: &str = "deadletterstage";
+
+/// Where the processing of the event failed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    /// The function failed with an error the caller shouldn't retry
+    Function,
+    /// The event emitted at most once couldn't be delivered to a target of
+    /// the emitter
+    Delivery,
+}
+
+impl Stage {
+    fn as_str(self) -> &'static str {
+        match self {
+            Stage::Function => "function",
+            Stage::Delivery => "delivery",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Stage> {
+        match s {
+            "function" => Some(Stage::Function),
+            "delivery" => Some(Stage::Delivery),
+            _ => None,
+        }
+    }
+}
+
+/// Event which couldn't be processed or delivered.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeadLetter {
+    pub event: Event,
+    pub stage: Stage,
+    pub error: String,
+    pub attempts: u32,
+}
+
+impl DeadLetter {
+    /// The original event, with the dead letter extensions.
+    pub fn to_event(&self) -> Event {
+        let mut event = self.event.clone();
+        event.extensions.insert(
+            String::from(STAGE_EXTENSION),
+            self.stage.as_str().to_string(),
+        );
+        event
+            .extensions
+            .insert(String::from(ERROR_EXTENSION), self.error.clone());
+        event
+            .extensions
+            .insert(String::from(ATTEMPTS_EXTENSION), self.attempts.to_string());
+        event
+    }
+
+    /// Restores the dead letter recorded by `to_event`.
+    pub fn from_event(mut event: Event) -> Option<DeadLetter> {
+        let stage = Stage::parse(&event.extensions.remove(STAGE_EXTENSION)?)?;
+        let error = event.extensions.remove(ERROR_EXTENSION)?;
+        let attempts = event.extensions.remove(ATTEMPTS_EXTENSION)?.parse().ok()?;
+        Some(DeadLetter {
+            event,
+            stage,
+            error,
+            attempts,
+        })
+    }
+}
+
+/// Records the events which couldn't be processed or delivered, so they can
+/// be replayed later. Use a `Sink` to send them to another service or a
+/// `FileTarget` to append them to a local NDJSON file.
+#[derive(Clone)]
+pub struct DeadLetterQueue {
+    target: Arc<dyn Target>,
+}
+
+impl DeadLetterQueue {
+    pub fn new<T: Target>(target: T) -> DeadLetterQueue {
+        DeadLetterQueue {
+            target: Arc::new(target),
+        }
+    }
+
+    /// Records the dead letter, logging the failures.
+    pub(crate) async fn record(&self, letter: DeadLetter) {
+        if let Err(e) = self.target.send(&letter.to_event()).await {
+            log::error!(
+                "FaaS Runtime: Cannot record dead letter of event {}: {}",
+                letter.event.id,
+                e
+            );
+        }
+    }
+}
+
+/// Reads the dead letters appended to the NDJSON file by a `FileTarget`.
+pub fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<DeadLetter>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str::<Event>(line)
+                .ok()
+                .and_then(DeadLetter::from_event)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid dead letter {}", line),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Sends the original events of the dead letters to `target`, e.g. a `Sink`
+/// pointing to the function. Returns the dead letters which failed again,
+/// with the error and the attempts updated.
+pub async fn replay<T: Target + ?Sized>(letters: Vec<DeadLetter>, target: &T) -> Vec<DeadLetter> {
+    let mut failed = Vec::new();
+    for mut letter in letters {
+        if let Err(e) = target.send(&letter.event).await {
+            letter.attempts += e.attempts;
+            letter.error = e.reason;
+            failed.push(letter);
+        }
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emitter::{FileTarget, MemoryTarget};
+
+    fn letter() -> DeadLetter {
+        DeadLetter {
+            event: Event::new(),
+            stage: Stage::Delivery,
+            error: String::from("the sink replied 503 Service Unavailable"),
+            attempts: 4,
+        }
+    }
+
+    #[test]
+    fn test_dead_letter_event_roundtrip() {
+        let letter = letter();
+        let event = letter.to_event();
+        assert_eq!(event.extensions.get(ATTEMPTS_EXTENSION).unwrap(), "4");
+
+        assert_eq!(DeadLetter::from_event(event), Some(letter.clone()));
+        assert_eq!(DeadLetter::from_event(letter.event), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_replay_dead_letters_file() {
+        let path =
+            std::env::temp_dir().join(format!("faas-dead-letters-{}.ndjson", uuid::Uuid::new_v4()));
+        let file = FileTarget::new(&path);
+        let letters = vec![letter(), letter()];
+        for letter in &letters {
+            file.send(&letter.to_event()).await.unwrap();
+        }
+
+        let read = read_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read, letters);
+
+        let memory = MemoryTarget::new();
+        assert!(replay(read, &memory).await.is_empty());
+        let replayed: Vec<Event> = letters.into_iter().map(|l| l.event).collect();
+        assert_eq!(memory.events(), replayed);
+    }
+}
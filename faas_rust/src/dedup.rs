@@ -0,0 +1,278 @@
+use actix_web::dev::{Body, ResponseBody};
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use cloudevent::Event;
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Default time the responses are cached.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(600);
+/// Default maximum responses in the `MemoryStore`.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Identity of an event delivered to a function: the id is unique for every
+/// source, and the same event can be delivered to the functions on different
+/// paths.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EventKey {
+    pub route: String,
+    pub source: String,
+    pub id: String,
+}
+
+impl EventKey {
+    pub fn of(route: &str, event: &Event) -> EventKey {
+        EventKey {
+            route: route.to_string(),
+            source: event.source.clone(),
+            id: event.id.clone(),
+        }
+    }
+}
+
+/// Response of a processed event, replayed to the duplicates.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CachedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl CachedResponse {
+    /// Copy of `res`, if its body is already in memory.
+    pub fn of(res: &HttpResponse) -> Option<CachedResponse> {
+        let body = match res.body() {
+            ResponseBody::Body(Body::Bytes(bytes)) | ResponseBody::Other(Body::Bytes(bytes)) => {
+                bytes.to_vec()
+            }
+            ResponseBody::Body(Body::None)
+            | ResponseBody::Body(Body::Empty)
+            | ResponseBody::Other(Body::None)
+            | ResponseBody::Other(Body::Empty) => Vec::new(),
+            _ => return None,
+        };
+        let headers = res
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.as_str().to_string(), value.to_string()))
+            })
+            .collect();
+        Some(CachedResponse {
+            status: res.status().as_u16(),
+            headers,
+            body,
+        })
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+        let mut builder = HttpResponse::build(status);
+        for (name, value) in &self.headers {
+            builder.header(name.as_str(), value.as_str());
+        }
+        builder.body(self.body.clone())
+    }
+}
+
+/// Storage of the responses of the processed events. Implement it to share
+/// them among the replicas of the function, e.g. in Redis.
+pub trait DedupStore: Send + Sync + 'static {
+    fn get<'a>(&'a self, key: &'a EventKey) -> LocalBoxFuture<'a, Option<CachedResponse>>;
+
+    /// Stores the response, which should be discarded after `ttl`.
+    fn put<'a>(
+        &'a self,
+        key: EventKey,
+        response: CachedResponse,
+        ttl: Duration,
+    ) -> LocalBoxFuture<'a, ()>;
+}
+
+/// In-memory `DedupStore` of each replica. When it's full the oldest
+/// responses are evicted.
+pub struct MemoryStore {
+    capacity: usize,
+    state: Mutex<MemoryState>,
+}
+
+struct MemoryState {
+    entries: HashMap<EventKey, (Instant, CachedResponse)>,
+    // Keys in insertion order, with their expiration
+    order: VecDeque<(Instant, EventKey)>,
+}
+
+impl MemoryStore {
+    pub fn new(capacity: usize) -> MemoryStore {
+        MemoryStore {
+            capacity,
+            state: Mutex::new(MemoryState {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl MemoryState {
+    fn evict_oldest(&mut self) {
+        if let Some((expires, key)) = self.order.pop_front() {
+            // The key could have been stored again after this entry
+            if self.entries.get(&key).map(|(e, _)| *e) == Some(expires) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+impl DedupStore for MemoryStore {
+    fn get<'a>(&'a self, key: &'a EventKey) -> LocalBoxFuture<'a, Option<CachedResponse>> {
+        let state = self.state.lock().unwrap();
+        let response = match state.entries.get(key) {
+            Some((expires, response)) if *expires > Instant::now() => Some(response.clone()),
+            _ => None,
+        };
+        future::ready(response).boxed_local()
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: EventKey,
+        response: CachedResponse,
+        ttl: Duration,
+    ) -> LocalBoxFuture<'a, ()> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        while state.order.front().is_some_and(|(e, _)| *e <= now) {
+            state.evict_oldest();
+        }
+        while state.entries.len() >= self.capacity && !state.order.is_empty() {
+            state.evict_oldest();
+        }
+        let expires = now + ttl;
+        state.order.push_back((expires, key.clone()));
+        state.entries.insert(key, (expires, response));
+        future::ready(()).boxed_local()
+    }
+}
+
+/// Processes every event once per path, keyed on its source and id: the
+/// successful response of the first invocation is replayed to the duplicates
+/// delivered within the TTL. Only the requests with a single event are
+/// deduplicated, and duplicates received while the first invocation is in
+/// progress are processed as well.
+#[derive(Clone)]
+pub struct Deduplicator {
+    store: Arc<dyn DedupStore>,
+    ttl: Duration,
+}
+
+impl Default for Deduplicator {
+    fn default() -> Self {
+        Deduplicator::new(MemoryStore::default())
+    }
+}
+
+impl Deduplicator {
+    pub fn new<S: DedupStore>(store: S) -> Deduplicator {
+        Deduplicator {
+            store: Arc::new(store),
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// Defaults to 10 minutes.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub(crate) async fn get(&self, key: &EventKey) -> Option<CachedResponse> {
+        self.store.get(key).await
+    }
+
+    pub(crate) async fn put(&self, key: EventKey, res: &HttpResponse) {
+        if !res.status().is_success() {
+            return;
+        }
+        if let Some(response) = CachedResponse::of(res) {
+            self.store.put(key, response, self.ttl).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn key(id: &str) -> EventKey {
+        EventKey {
+            route: String::from("/orders"),
+            source: String::from("/shop"),
+            id: String::from(id),
+        }
+    }
+
+    #[test]
+    fn test_replay_cached_response() {
+        let dedup = Deduplicator::default();
+        let res = HttpResponse::Ok()
+            .content_type("application/json")
+            .body("{\"total\":10}");
+
+        block_on(async {
+            assert_eq!(dedup.get(&key("1")).await, None);
+            dedup.put(key("1"), &res).await;
+            dedup
+                .put(key("2"), &HttpResponse::BadRequest().finish())
+                .await;
+
+            let cached = dedup.get(&key("1")).await.unwrap();
+            assert_eq!(cached.body, b"{\"total\":10}".to_vec());
+            let replayed = cached.to_response();
+            assert_eq!(replayed.status(), StatusCode::OK);
+            assert_eq!(
+                replayed.headers().get("content-type").unwrap(),
+                "application/json"
+            );
+            assert_eq!(dedup.get(&key("2")).await, None);
+        });
+    }
+
+    #[test]
+    fn test_memory_store_bounds() {
+        let store = MemoryStore::new(2);
+        let response = CachedResponse {
+            status: 202,
+            headers: vec![],
+            body: vec![],
+        };
+        let ttl = Duration::from_secs(60);
+
+        block_on(async {
+            store.put(key("1"), response.clone(), ttl).await;
+            store.put(key("2"), response.clone(), ttl).await;
+            store.put(key("3"), response.clone(), ttl).await;
+            assert_eq!(store.get(&key("1")).await, None);
+            assert!(store.get(&key("3")).await.is_some());
+
+            store
+                .put(key("4"), response.clone(), Duration::from_secs(0))
+                .await;
+            assert_eq!(store.get(&key("4")).await, None);
+        });
+    }
+}
@@ -0,0 +1,302 @@
+use crate::dead_letter::{DeadLetter, DeadLetterQueue, Stage};
+use crate::error::FunctionError;
+use crate::shutdown::PendingWork;
+use crate::sink::{DeliveryError, Sink};
+use crate::trace;
+use actix_web::error::BlockingError;
+use actix_web::http::StatusCode;
+use actix_web::web::{self, Data};
+use actix_web::HttpRequest;
+use cloudevent::Event;
+use futures::future::{FutureExt, LocalBoxFuture};
+use opentelemetry::trace::SpanContext;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Destination of the events emitted by the functions.
+pub trait Target: Send + Sync + 'static {
+    /// Sends the event, retrying the transient failures if possible.
+    fn send<'a>(&'a self, event: &'a Event) -> LocalBoxFuture<'a, Result<(), DeliveryError>>;
+}
+
+impl Target for Sink {
+    fn send<'a>(&'a self, event: &'a Event) -> LocalBoxFuture<'a, Result<(), DeliveryError>> {
+        Sink::send(self, event).boxed_local()
+    }
+}
+
+/// Appends the events to a file, one JSON object per line.
+#[derive(Clone, Debug)]
+pub struct FileTarget {
+    path: PathBuf,
+}
+
+impl FileTarget {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileTarget {
+        FileTarget { path: path.into() }
+    }
+}
+
+impl Target for FileTarget {
+    fn send<'a>(&'a self, event: &'a Event) -> LocalBoxFuture<'a, Result<(), DeliveryError>> {
+        let path = self.path.clone();
+        let line = serde_json::to_vec(event).map(|mut line| {
+            line.push(b'\n');
+            line
+        });
+        async move {
+            let line = line.map_err(|e| e.to_string())?;
+            // Files are written on the thread pool, not to block the worker
+            web::block(move || {
+                // A single write keeps the lines of concurrent invocations whole
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .and_then(|mut file| file.write_all(&line))
+                    .map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+            })
+            .await
+            .map_err(|e| match e {
+                BlockingError::Error(reason) => reason,
+                BlockingError::Canceled => String::from("Thread pool is gone"),
+            })
+        }
+        .map(|result| {
+            result.map_err(|reason| DeliveryError {
+                attempts: 1,
+                reason,
+            })
+        })
+        .boxed_local()
+    }
+}
+
+/// Keeps the events in memory, to verify the emitted events in the tests.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTarget {
+    events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl MemoryTarget {
+    pub fn new() -> MemoryTarget {
+        MemoryTarget::default()
+    }
+
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl Target for MemoryTarget {
+    fn send<'a>(&'a self, event: &'a Event) -> LocalBoxFuture<'a, Result<(), DeliveryError>> {
+        self.events.lock().unwrap().push(event.clone());
+        futures::future::ok(()).boxed_local()
+    }
+}
+
+/// Delivery guarantee of an emitted event.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delivery {
+    /// The event is sent in the background and the failures are only
+    /// logged.
+    AtMostOnce,
+    /// `emit` completes when the target accepted the event, and fails if it
+    /// didn't after the retries.
+    AtLeastOnce,
+}
+
+/// Sends events to the registered targets while the function is running.
+/// Functions can access it declaring an argument of type `Emitter`.
+///
+/// ```ignore
+/// emitter.emit("audit", event, Delivery::AtLeastOnce).await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct Emitter {
+    targets: HashMap<String, Arc<dyn Target>>,
+    span_context: Option<SpanContext>,
+    dead_letters: Option<DeadLetterQueue>,
+    pending: Option<PendingWork>,
+}
+
+impl Emitter {
+    pub fn new() -> Emitter {
+        Emitter::default()
+    }
+
+    /// Registers `target` with `name`.
+    pub fn target<S: Into<String>, T: Target>(mut self, name: S, target: T) -> Self {
+        self.targets.insert(name.into(), Arc::new(target));
+        self
+    }
+
+    /// Emitter registered in the runtime, propagating the trace context of
+    /// the invocation in progress. The events emitted at most once which
+    /// can't be delivered are recorded in the `DeadLetterQueue`, if any.
+    pub fn from_request(req: &HttpRequest) -> Emitter {
+        let mut emitter = req
+            .app_data::<Data<Emitter>>()
+            .map(|e| e.get_ref().clone())
+            .unwrap_or_default();
+        emitter.span_context = req.extensions().get::<SpanContext>().cloned();
+        emitter.dead_letters = req
+            .app_data::<Data<DeadLetterQueue>>()
+            .map(|d| d.get_ref().clone());
+        emitter.pending = req
+            .app_data::<Data<PendingWork>>()
+            .map(|p| p.get_ref().clone());
+        emitter
+    }
+
+    /// Sends `event` to the target registered with `name`.
+    pub async fn emit(
+        &self,
+        name: &str,
+        mut event: Event,
+        delivery: Delivery,
+    ) -> Result<(), EmitError> {
+        let target = self
+            .targets
+            .get(name)
+            .cloned()
+            .ok_or_else(|| EmitError::UnknownTarget(name.to_string()))?;
+        if let Some(span_context) = &self.span_context {
+            trace::inject(std::slice::from_mut(&mut event), span_context);
+        }
+
+        match delivery {
+            Delivery::AtMostOnce => {
+                let name = name.to_string();
+                let dead_letters = self.dead_letters.clone();
+                let task = async move {
+                    if let Err(e) = target.send(&event).await {
+                        log::error!(
+                            "FaaS Runtime: Cannot emit event {} to {}: {}",
+                            event.id,
+                            name,
+                            e
+                        );
+                        if let Some(dead_letters) = dead_letters {
+                            dead_letters
+                                .record(DeadLetter {
+                                    event,
+                                    stage: Stage::Delivery,
+                                    error: e.reason,
+                                    attempts: e.attempts,
+                                })
+                                .await;
+                        }
+                    }
+                };
+                // The runtime waits for the tracked tasks before stopping
+                match &self.pending {
+                    Some(pending) => pending.spawn(task),
+                    None => actix_rt::spawn(task),
+                }
+                Ok(())
+            }
+            Delivery::AtLeastOnce => target.send(&event).await.map_err(EmitError::Delivery),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EmitError {
+    UnknownTarget(String),
+    Delivery(DeliveryError),
+}
+
+impl fmt::Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmitError::UnknownTarget(name) => write!(f, "Unknown emitter target {}", name),
+            EmitError::Delivery(e) => write!(f, "Cannot emit event: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EmitError {}
+
+impl FunctionError for EmitError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EmitError::UnknownTarget(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            EmitError::Delivery(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    struct FailingTarget;
+
+    impl Target for FailingTarget {
+        fn send<'a>(&'a self, _event: &'a Event) -> LocalBoxFuture<'a, Result<(), DeliveryError>> {
+            futures::future::err(DeliveryError {
+                attempts: 1,
+                reason: String::from("unavailable"),
+            })
+            .boxed_local()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_emit_by_delivery_guarantee() {
+        let memory = MemoryTarget::new();
+        let emitter = Emitter::new()
+            .target("memory", memory.clone())
+            .target("failing", FailingTarget);
+
+        let event = Event::new();
+        emitter
+            .emit("memory", event.clone(), Delivery::AtLeastOnce)
+            .await
+            .unwrap();
+        assert_eq!(memory.events(), vec![event.clone()]);
+
+        assert!(emitter
+            .emit("failing", event.clone(), Delivery::AtMostOnce)
+            .await
+            .is_ok());
+        assert!(matches!(
+            emitter
+                .emit("failing", event.clone(), Delivery::AtLeastOnce)
+                .await,
+            Err(EmitError::Delivery(_))
+        ));
+        assert_eq!(
+            emitter.emit("missing", event, Delivery::AtLeastOnce).await,
+            Err(EmitError::UnknownTarget(String::from("missing")))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_file_target_appends_lines() {
+        let path =
+            std::env::temp_dir().join(format!("faas-emitter-{}.ndjson", uuid::Uuid::new_v4()));
+        let emitter = Emitter::new().target("file", FileTarget::new(&path));
+
+        for _ in 0..2 {
+            emitter
+                .emit("file", Event::new(), Delivery::AtLeastOnce)
+                .await
+                .unwrap();
+        }
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let events: Vec<Event> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+    }
+}
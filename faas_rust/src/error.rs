@@ -0,0 +1,432 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use cloudevent::{Event, Writer};
+use serde::Serialize;
+use std::fmt;
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+pub const DEFAULT_ERROR_EVENT_TYPE: &str = "dev.knative.function.error";
+/// Extension of the error events carrying the id of the failed event.
+pub const CORRELATION_ID_EXTENSION: &str = "correlationid";
+
+/// Error of a function, describing how it should be reported to the caller.
+///
+/// Functions can return any error type implementing `FunctionError`,
+/// `actix_web::ResponseError` or `std::error::Error`: the latter are reported
+/// as `500 Internal Server Error`.
+pub trait FunctionError: fmt::Display {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    /// If the caller can retry the invocation. By default server errors and
+    /// `429 Too Many Requests` are retryable.
+    fn retryable(&self) -> bool {
+        let status = self.status_code();
+        status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+    }
+
+    /// Additional details included in the error response.
+    fn details(&self) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+/// Ready to use `FunctionError`.
+///
+/// ```ignore
+/// Err(Error::new(StatusCode::UNPROCESSABLE_ENTITY, "Missing name").details(json!({"field": "name"})))
+/// ```
+#[derive(Debug, Clone)]
+pub struct Error {
+    status: StatusCode,
+    message: String,
+    retryable: Option<bool>,
+    details: Option<serde_json::Value>,
+}
+
+impl Error {
+    pub fn new<S: Into<String>>(status: StatusCode, message: S) -> Error {
+        Error {
+            status,
+            message: message.into(),
+            retryable: None,
+            details: None,
+        }
+    }
+
+    pub fn bad_request<S: Into<String>>(message: S) -> Error {
+        Error::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn unprocessable<S: Into<String>>(message: S) -> Error {
+        Error::new(StatusCode::UNPROCESSABLE_ENTITY, message)
+    }
+
+    pub fn internal<S: Into<String>>(message: S) -> Error {
+        Error::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+
+    pub fn retryable(mut self, retryable: bool) -> Error {
+        self.retryable = Some(retryable);
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> Error {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl FunctionError for Error {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn retryable(&self) -> bool {
+        match self.retryable {
+            Some(retryable) => retryable,
+            None => self.status.is_server_error() || self.status == StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        self.details.clone()
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        Problem::from_function_error(self).error_response()
+    }
+}
+
+/// Error response body, as defined in https://tools.ietf.org/html/rfc7807
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub retryable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    /// Seconds, sent in the `Retry-After` header
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
+}
+
+impl Problem {
+    pub fn new<S: Into<String>>(status: StatusCode, detail: S) -> Problem {
+        Problem {
+            problem_type: String::from("about:blank"),
+            title: status
+                .canonical_reason()
+                .unwrap_or("Unknown Error")
+                .to_string(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            retryable: status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            details: None,
+            retry_after: None,
+        }
+    }
+
+    pub fn retry_after(mut self, seconds: u64) -> Problem {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    pub fn from_function_error<E: FunctionError + ?Sized>(e: &E) -> Problem {
+        Problem {
+            retryable: e.retryable(),
+            details: e.details(),
+            ..Problem::new(e.status_code(), e.to_string())
+        }
+    }
+
+    /// Problem describing `e`.
+    pub fn of(e: &actix_web::Error) -> Problem {
+        if let Some(problem) = e.as_error::<Problem>() {
+            return problem.clone();
+        }
+        if let Some(error) = e.as_error::<Error>() {
+            return Problem::from_function_error(error);
+        }
+        Problem::new(e.as_response_error().status_code(), e.to_string())
+    }
+
+    /// Converts an error into a problem, unless its response is already one.
+    pub(crate) fn from_error(e: actix_web::Error) -> actix_web::Error {
+        let response = e.as_response_error().error_response();
+        let is_problem = response
+            .headers()
+            .get("content-type")
+            .and_then(|ct| ct.to_str().ok())
+            .map(|ct| ct.starts_with(PROBLEM_CONTENT_TYPE))
+            .unwrap_or(false);
+        if is_problem {
+            e
+        } else {
+            Problem::new(response.status(), e.to_string()).into()
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.title, self.detail)
+    }
+}
+
+impl ResponseError for Problem {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut builder = HttpResponse::build(ResponseError::status_code(self));
+        if let Some(retry_after) = self.retry_after {
+            builder.header(header::RETRY_AFTER, retry_after.to_string());
+        }
+        builder
+            .content_type(PROBLEM_CONTENT_TYPE)
+            .body(serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+/// Reports the failed invocations as cloud events, with the problem as data,
+/// instead of `application/problem+json` responses.
+#[derive(Clone, Debug, Default)]
+pub struct ErrorEvents {
+    pub event_type: Option<String>,
+    /// Defaults to the hostname
+    pub source: Option<String>,
+}
+
+impl ErrorEvents {
+    /// Error event describing `problem`. `correlation_id` is the id of the
+    /// event which failed, if any.
+    pub fn to_event(&self, problem: &Problem, correlation_id: Option<&str>) -> Event {
+        let mut event = Event::new();
+        event.event_type = self
+            .event_type
+            .clone()
+            .unwrap_or_else(|| String::from(DEFAULT_ERROR_EVENT_TYPE));
+        if let Some(source) = &self.source {
+            event.source = source.clone();
+        }
+        if let Some(correlation_id) = correlation_id {
+            event.extensions.insert(
+                String::from(CORRELATION_ID_EXTENSION),
+                correlation_id.to_string(),
+            );
+        }
+        if let Ok(data) = serde_json::to_value(problem) {
+            let _ = event.write_payload("application/json", data);
+        }
+        event
+    }
+}
+
+/// Conversion of the function errors, used by the code generated by
+/// `faas_function`. The error type is resolved at compile time with the
+/// autoref specialization: `FunctionError` first, then `Into<actix_web::Error>`,
+/// then `std::error::Error`.
+#[doc(hidden)]
+#[allow(clippy::wrong_self_convention)]
+pub mod dispatch {
+    use super::{FunctionError, Problem};
+    use actix_web::http::StatusCode;
+    use std::cell::Cell;
+
+    pub struct ErrorWrapper<E>(pub Cell<Option<E>>);
+
+    impl<E> ErrorWrapper<E> {
+        pub fn new(e: E) -> ErrorWrapper<E> {
+            ErrorWrapper(Cell::new(Some(e)))
+        }
+
+        fn take(&self) -> E {
+            self.0.take().expect("Error already converted")
+        }
+    }
+
+    pub trait FromFunctionError {
+        fn into_runtime_error(&self) -> actix_web::Error;
+    }
+
+    impl<E: FunctionError> FromFunctionError for &&ErrorWrapper<E> {
+        fn into_runtime_error(&self) -> actix_web::Error {
+            Problem::from_function_error(&self.take()).into()
+        }
+    }
+
+    pub trait FromActixError {
+        fn into_runtime_error(&self) -> actix_web::Error;
+    }
+
+    impl<E: Into<actix_web::Error>> FromActixError for &ErrorWrapper<E> {
+        fn into_runtime_error(&self) -> actix_web::Error {
+            self.take().into()
+        }
+    }
+
+    pub trait FromStdError {
+        fn into_runtime_error(&self) -> actix_web::Error;
+    }
+
+    impl<E: std::error::Error> FromStdError for ErrorWrapper<E> {
+        fn into_runtime_error(&self) -> actix_web::Error {
+            Problem::new(StatusCode::INTERNAL_SERVER_ERROR, self.take().to_string()).into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::dev::Body;
+    use cloudevent::Reader;
+
+    /// Converts `e` as the code generated by `faas_function` does.
+    macro_rules! runtime_error {
+        ($e:expr) => {{
+            #[allow(unused_imports)]
+            use dispatch::{FromActixError, FromFunctionError, FromStdError};
+            (&&&dispatch::ErrorWrapper::new($e)).into_runtime_error()
+        }};
+    }
+
+    struct OutOfStock;
+
+    impl fmt::Display for OutOfStock {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("Out of stock")
+        }
+    }
+
+    impl FunctionError for OutOfStock {
+        fn status_code(&self) -> StatusCode {
+            StatusCode::CONFLICT
+        }
+    }
+
+    #[derive(Debug)]
+    struct Unauthorized;
+
+    impl fmt::Display for Unauthorized {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("Unauthorized")
+        }
+    }
+
+    impl ResponseError for Unauthorized {
+        fn status_code(&self) -> StatusCode {
+            StatusCode::UNAUTHORIZED
+        }
+    }
+
+    #[derive(Debug)]
+    struct DiskFull;
+
+    impl fmt::Display for DiskFull {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("Disk full")
+        }
+    }
+
+    impl std::error::Error for DiskFull {}
+
+    fn problem_json(res: &HttpResponse) -> serde_json::Value {
+        match res.body().as_ref() {
+            Some(Body::Bytes(bytes)) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("Unexpected body"),
+        }
+    }
+
+    #[test]
+    fn test_function_error_status() {
+        let res = runtime_error!(OutOfStock)
+            .as_response_error()
+            .error_response();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            PROBLEM_CONTENT_TYPE
+        );
+        let body = problem_json(&res);
+        assert_eq!(body["type"], "about:blank");
+        assert_eq!(body["title"], "Conflict");
+        assert_eq!(body["status"], 409);
+        assert_eq!(body["detail"], "Out of stock");
+        assert_eq!(body["retryable"], false);
+    }
+
+    #[test]
+    fn test_actix_error_keeps_status() {
+        let res = runtime_error!(Unauthorized)
+            .as_response_error()
+            .error_response();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_std_error_is_internal_server_error() {
+        let res = runtime_error!(DiskFull)
+            .as_response_error()
+            .error_response();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = problem_json(&res);
+        assert_eq!(body["title"], "Internal Server Error");
+        assert_eq!(body["status"], 500);
+        assert_eq!(body["detail"], "Disk full");
+        assert_eq!(body["retryable"], true);
+    }
+
+    #[test]
+    fn test_error_details_and_retry_after() {
+        let e = Error::unprocessable("Missing name").details(serde_json::json!({"field": "name"}));
+        let res = runtime_error!(e).as_response_error().error_response();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem_json(&res)["details"]["field"], "name");
+
+        let res = Problem::new(StatusCode::TOO_MANY_REQUESTS, "Slow down")
+            .retry_after(5)
+            .error_response();
+        assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "5");
+    }
+
+    #[test]
+    fn test_error_event_correlates_failed_event() {
+        let error_events = ErrorEvents {
+            event_type: None,
+            source: Some(String::from("/functions/orders")),
+        };
+        let problem = Problem::new(StatusCode::UNPROCESSABLE_ENTITY, "Missing name");
+
+        let event = error_events.to_event(&problem, Some("1234"));
+
+        assert_eq!(event.event_type, DEFAULT_ERROR_EVENT_TYPE);
+        assert_eq!(event.source, "/functions/orders");
+        assert_eq!(
+            event.extensions.get(CORRELATION_ID_EXTENSION),
+            Some(&String::from("1234"))
+        );
+        let data = event.read_payload().unwrap().unwrap();
+        assert_eq!(data["status"], 422);
+        assert_eq!(data["detail"], "Missing name");
+        assert_eq!(data["retryable"], false);
+    }
+}
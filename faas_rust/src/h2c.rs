@@ -0,0 +1,207 @@
+use actix_http::body::MessageBody;
+use actix_http::error::DispatchError;
+use actix_http::http::{header, HeaderValue};
+use actix_http::{HttpService, Protocol, Request, Response};
+use actix_rt::net::{TcpStream, UnixStream};
+use actix_server::Server;
+use actix_service::{
+    apply_fn_factory, fn_service, map_config, pipeline_factory, IntoServiceFactory, Service,
+    ServiceFactory,
+};
+use actix_web::dev::AppConfig;
+use futures::future::ok;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::net::UnixListener;
+use std::time::Duration;
+
+/// Connection preface of the HTTP/2 clients with prior knowledge.
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+/// Time to send the first bytes, like the client timeout of `HttpServer`.
+const DETECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DETECT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Starts a server accepting both HTTP/1.1 and HTTP/2 cleartext connections
+/// on the TCP listeners, serving the app created by `factory`. Like
+/// `HttpServer`, every worker creates its own app. The Unix Domain Sockets
+/// accept only HTTP/1.1.
+pub(crate) fn server<F, I, S, B>(
+    tcp_listeners: Vec<TcpListener>,
+    uds_listeners: Vec<UnixListener>,
+    workers: Option<usize>,
+    shutdown_timeout: Option<u64>,
+    factory: F,
+) -> io::Result<Server>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: IntoServiceFactory<S>,
+    S: ServiceFactory<Config = AppConfig, Request = Request>,
+    S::Error: Into<actix_web::Error> + 'static,
+    S::InitError: fmt::Debug,
+    S::Response: Into<Response<B>> + 'static,
+    <S::Service as Service>::Future: 'static,
+    B: MessageBody + 'static,
+{
+    let mut builder = Server::build().disable_signals();
+    if let Some(workers) = workers {
+        builder = builder.workers(workers);
+    }
+    if let Some(shutdown_timeout) = shutdown_timeout {
+        builder = builder.shutdown_timeout(shutdown_timeout);
+    }
+
+    for tcp_listener in tcp_listeners {
+        let factory = factory.clone();
+        // Only HttpServer can create the AppConfig with the address of the
+        // listener, so it's set as the host of the requests without one
+        let host = HeaderValue::from_str(&tcp_listener.local_addr()?.to_string()).ok();
+        builder = builder.listen("faas-h2c", tcp_listener, move || {
+            let host = host.clone();
+            pipeline_factory(fn_service(detect_protocol)).and_then(HttpService::build().finish(
+                apply_fn_factory(
+                    map_config(factory(), |_| AppConfig::default()),
+                    move |mut req: Request, srv| {
+                        set_default_host(&mut req, host.as_ref());
+                        srv.call(req)
+                    },
+                ),
+            ))
+        })?;
+    }
+    for uds_listener in uds_listeners {
+        let factory = factory.clone();
+        builder = builder.listen_uds("faas-uds", uds_listener, move || {
+            pipeline_factory(fn_service(|io: UnixStream| {
+                ok::<_, DispatchError>((io, Protocol::Http1, None))
+            }))
+            .and_then(HttpService::build().finish(map_config(factory(), |_| AppConfig::default())))
+        })?;
+    }
+    Ok(builder.run())
+}
+
+/// Peeks the first bytes of the connection, without consuming them, to
+/// detect the HTTP/2 preface. Connections not sending them in time are
+/// closed.
+async fn detect_protocol(
+    io: TcpStream,
+) -> Result<(TcpStream, Protocol, Option<SocketAddr>), DispatchError> {
+    actix_rt::time::timeout(DETECT_TIMEOUT, peek_protocol(io))
+        .await
+        .unwrap_or(Err(DispatchError::SlowRequestTimeout))
+}
+
+async fn peek_protocol(
+    mut io: TcpStream,
+) -> Result<(TcpStream, Protocol, Option<SocketAddr>), DispatchError> {
+    let peer_addr = io.peer_addr().ok();
+    let mut buf = [0; 24];
+    loop {
+        let read = io.peek(&mut buf).await?;
+        if let Some(protocol) = protocol(&buf[..read]) {
+            return Ok((io, protocol, peer_addr));
+        }
+        // Wait for the rest of the preface
+        actix_rt::time::delay_for(DETECT_INTERVAL).await;
+    }
+}
+
+fn set_default_host(req: &mut Request, host: Option<&HeaderValue>) {
+    if let Some(host) = host {
+        if req.uri().authority().is_none() && !req.head().headers.contains_key(header::HOST) {
+            req.head_mut().headers.insert(header::HOST, host.clone());
+        }
+    }
+}
+
+/// Protocol of a connection starting with `bytes`, or `None` if more bytes
+/// are required to detect it.
+fn protocol(bytes: &[u8]) -> Option<Protocol> {
+    if bytes.is_empty() || !H2_PREFACE.starts_with(bytes) {
+        // Closed connections are handled by the HTTP/1.1 dispatcher too
+        Some(Protocol::Http1)
+    } else if bytes.len() == H2_PREFACE.len() {
+        Some(Protocol::Http2)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invocation;
+    use actix_web::{web, App, HttpRequest};
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_detect_protocol() {
+        assert!(matches!(
+            protocol(b"POST / HTTP/1.1\r\nHost: localhost\r\n"),
+            Some(Protocol::Http1)
+        ));
+        assert!(matches!(protocol(H2_PREFACE), Some(Protocol::Http2)));
+        assert!(protocol(&H2_PREFACE[..10]).is_none());
+        assert!(matches!(protocol(b"PRI * HTTP/1.1"), Some(Protocol::Http1)));
+    }
+
+    /// Sends `request` over a new HTTP/1.1 connection, returning the response.
+    fn http1(addr: SocketAddr, request: &str) -> String {
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[actix_rt::test]
+    async fn test_events_on_http1_and_h2c() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let srv = server(vec![listener], vec![], Some(1), None, || {
+            App::new().route(
+                "/",
+                web::post().to(|req: HttpRequest, payload: web::Payload| {
+                    invocation::handle(req, payload, |_, events| async move { Ok(events) })
+                }),
+            )
+        })
+        .unwrap();
+        let headers = [
+            ("ce-id", "1"),
+            ("ce-source", "/shop"),
+            ("ce-type", "dev.knative.order"),
+            ("ce-specversion", "1.0"),
+        ];
+
+        let mut request = String::from("POST / HTTP/1.1\r\nconnection: close\r\n");
+        for (name, value) in &headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        let response = http1(addr, &request);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("ce-type: dev.knative.order"));
+
+        // HTTP/2 with prior knowledge
+        let url = format!("http://{}/", addr);
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let (client, connection) = h2::client::handshake(tcp).await.unwrap();
+        actix_rt::spawn(async move {
+            let _ = connection.await;
+        });
+        let mut h2c = http::Request::post(url.as_str());
+        for (name, value) in &headers {
+            h2c = h2c.header(*name, *value);
+        }
+        let mut client = client.ready().await.unwrap();
+        let (res, _) = client.send_request(h2c.body(()).unwrap(), true).unwrap();
+        let res = res.await.unwrap();
+        assert_eq!(res.version(), http::Version::HTTP_2);
+        assert_eq!(res.status(), http::StatusCode::OK);
+        assert_eq!(res.headers().get("ce-type").unwrap(), "dev.knative.order");
+
+        srv.stop(false).await;
+    }
+}
@@ -0,0 +1,97 @@
+use actix_web::{web, HttpResponse};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+pub const DEFAULT_LIVENESS_PATH: &str = "/health/liveness";
+pub const DEFAULT_READINESS_PATH: &str = "/health/readiness";
+
+/// Readiness of the runtime. It becomes ready when all the init hooks are
+/// completed and not ready again when the runtime starts shutting down.
+#[derive(Clone, Default)]
+pub struct Health {
+    ready: Arc<AtomicBool>,
+}
+
+impl Health {
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::SeqCst)
+    }
+}
+
+pub(crate) fn configure(
+    cfg: &mut web::ServiceConfig,
+    liveness_path: &str,
+    readiness_path: &str,
+    health: Health,
+) {
+    cfg.data(health)
+        .route(liveness_path, web::get().to(liveness))
+        .route(readiness_path, web::get().to(readiness));
+}
+
+async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+async fn readiness(health: web::Data<Health>) -> HttpResponse {
+    if health.is_ready() {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::ServiceUnavailable().finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+
+    /// Status of the probe served on `path`.
+    macro_rules! probe {
+        ($app:expr, $path:expr) => {
+            test::call_service(&mut $app, TestRequest::get().uri($path).to_request())
+                .await
+                .status()
+        };
+    }
+
+    #[actix_rt::test]
+    async fn test_probes() {
+        let health = Health::default();
+        let probes = health.clone();
+        let mut app = test::init_service(App::new().configure(move |cfg| {
+            configure(
+                cfg,
+                DEFAULT_LIVENESS_PATH,
+                DEFAULT_READINESS_PATH,
+                probes.clone(),
+            )
+        }))
+        .await;
+
+        // Not ready until the init hooks are completed
+        assert_eq!(probe!(app, DEFAULT_LIVENESS_PATH), StatusCode::OK);
+        assert_eq!(
+            probe!(app, DEFAULT_READINESS_PATH),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        health.set_ready(true);
+        assert_eq!(probe!(app, DEFAULT_LIVENESS_PATH), StatusCode::OK);
+        assert_eq!(probe!(app, DEFAULT_READINESS_PATH), StatusCode::OK);
+
+        // Draining
+        health.set_ready(false);
+        assert_eq!(probe!(app, DEFAULT_LIVENESS_PATH), StatusCode::OK);
+        assert_eq!(
+            probe!(app, DEFAULT_READINESS_PATH),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
@@ -0,0 +1,611 @@
+use crate::context::{Context, InvocationTimeout};
+use crate::dead_letter::{DeadLetter, DeadLetterQueue, Stage};
+use crate::dedup::{Deduplicator, EventKey};
+use crate::error::{ErrorEvents, Problem};
+use crate::limit::{ConcurrencyLimiter, Permit};
+use crate::logging::{self, LogContext};
+use crate::metrics::Metrics;
+use crate::payload::{self, PayloadLimits};
+use crate::request_reader::read_cloud_event;
+use crate::response_writer::{self, write_cloud_event, ResponseEncoding};
+use crate::shutdown::PendingWork;
+use crate::sink::Sink;
+use crate::trace::{self, Tracer};
+use actix_web::http::{header, HeaderValue, StatusCode};
+use actix_web::web::{Data, Payload};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use cloudevent::http::Encoding;
+use cloudevent::Event;
+use futures::FutureExt;
+use opentelemetry::trace::{Span, SpanContext, StatusCode as SpanStatus};
+use opentelemetry::KeyValue;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::time::{Duration, Instant};
+
+/// Handles a function invocation: reads the input events from the request,
+/// invokes `function` and writes the output events in the response or, if
+/// `Sink` is registered, sends them to the sink before replying. Errors are
+/// reported as `application/problem+json` or, if `ErrorEvents` is
+/// registered, as error events. If `Deduplicator` is registered, duplicate
+/// events get the cached response without invoking `function` again.
+///
+/// This is the entrypoint of the handlers generated by `faas_function`.
+pub async fn handle<F, Fut>(
+    req: HttpRequest,
+    payload: Payload,
+    function: F,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    F: FnOnce(HttpRequest, Vec<Event>) -> Fut,
+    Fut: Future<Output = Result<Vec<Event>, actix_web::Error>>,
+{
+    let metrics = req.app_data::<Data<Metrics>>().cloned();
+    let tracer = req.app_data::<Data<Tracer>>().cloned();
+    let _in_flight = metrics.as_ref().map(|m| m.in_flight());
+    let _pending = req.app_data::<Data<PendingWork>>().map(|p| p.start());
+    let start = Instant::now();
+
+    let result = invoke(req, payload, function, metrics.as_ref(), tracer.as_ref())
+        .await
+        .map_err(Problem::from_error);
+
+    if let Some(metrics) = &metrics {
+        metrics.observe_request(status(&result), start.elapsed());
+    }
+
+    result
+}
+
+async fn invoke<F, Fut>(
+    req: HttpRequest,
+    payload: Payload,
+    function: F,
+    metrics: Option<&Data<Metrics>>,
+    tracer: Option<&Data<Tracer>>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    F: FnOnce(HttpRequest, Vec<Event>) -> Fut,
+    Fut: Future<Output = Result<Vec<Event>, actix_web::Error>>,
+{
+    let request_encoding = request_encoding(&req);
+    let limits = req
+        .app_data::<Data<PayloadLimits>>()
+        .map(|limits| *limits.get_ref())
+        .unwrap_or_default();
+    let body = match payload::read_body(&req, payload, &request_encoding, &limits).await {
+        Ok(body) => body,
+        Err(e) => return report_error(&req, e, None, None, None),
+    };
+
+    let (encoding, events) = match read_cloud_event(req.clone(), body).await {
+        Ok(Some((encoding, events))) => (Some(encoding), events),
+        Ok(None) => (None, vec![]),
+        Err(e) => {
+            if let Some(metrics) = metrics {
+                metrics.parse_failure(payload::encoding_label(&request_encoding));
+            }
+            return report_error(&req, e, None, None, None);
+        }
+    };
+
+    if let Some(metrics) = metrics {
+        for event in &events {
+            metrics.event(&event.event_type);
+        }
+    }
+
+    let dedup = match (req.app_data::<Data<Deduplicator>>(), events.as_slice()) {
+        (Some(dedup), [event]) => Some((dedup.clone(), EventKey::of(req.path(), event))),
+        _ => None,
+    };
+    if let Some((dedup, key)) = &dedup {
+        if let Some(cached) = dedup.get(key).await {
+            log::debug!(
+                "FaaS Runtime: Replaying the response of duplicate event {} from {}",
+                key.id,
+                key.source
+            );
+            if let Some(metrics) = metrics {
+                metrics.duplicate();
+            }
+            return Ok(cached.to_response());
+        }
+    }
+
+    let span = tracer.map(|tracer| {
+        let parent = trace::extract(req.headers(), events.first());
+        let mut span = tracer.start_span(req.path(), &parent);
+        if let Some(event) = events.first() {
+            span.set_attribute(KeyValue::new("cloudevents.event_id", event.id.clone()));
+            span.set_attribute(KeyValue::new(
+                "cloudevents.event_type",
+                event.event_type.clone(),
+            ));
+            span.set_attribute(KeyValue::new(
+                "cloudevents.event_source",
+                event.source.clone(),
+            ));
+        }
+        req.extensions_mut().insert(span.span_context().clone());
+        span
+    });
+
+    let log_context = LogContext {
+        event_id: events.first().map(|e| e.id.clone()),
+        event_source: events.first().map(|e| e.source.clone()),
+        event_type: events.first().map(|e| e.event_type.clone()),
+        trace_id: span
+            .as_ref()
+            .map(|s| s.span_context().trace_id().to_string()),
+    };
+
+    let correlation_id = events.first().map(|e| e.id.clone());
+    let event_type = events.first().map(|e| e.event_type.clone());
+    let dead_letters = req
+        .app_data::<Data<DeadLetterQueue>>()
+        .map(|d| (d.get_ref().clone(), events.clone()));
+    let output = match acquire_permits(&req, event_type.as_deref(), metrics).await {
+        Ok(_permits) => {
+            // The invocation timeout starts when the function can run, the
+            // time waiting in the queue is bounded by the queue timeout.
+            // The deadline of the request is absolute, it counts the queue too.
+            let timeout = req.app_data::<Data<InvocationTimeout>>().map(|t| t.0);
+            let context = Context::new(&req, timeout);
+            req.extensions_mut().insert(context.clone());
+
+            let invocation = logging::scope(
+                log_context,
+                call_function(function, req.clone(), events, metrics),
+            );
+            // Dropping the invocation when the deadline expires cancels the function
+            match context.remaining() {
+                Some(remaining) if remaining == Duration::from_secs(0) => {
+                    Err(deadline_exceeded(metrics))
+                }
+                Some(remaining) => actix_rt::time::timeout(remaining, invocation)
+                    .await
+                    .unwrap_or_else(|_| Err(deadline_exceeded(metrics))),
+                None => invocation.await,
+            }
+        }
+        Err(e) => Err(e),
+    };
+    let result = match output {
+        Ok(mut output) => {
+            if let Some(span) = &span {
+                trace::inject(&mut output, span.span_context());
+            }
+            match req.app_data::<Data<Sink>>() {
+                Some(sink) => match sink.deliver(&output).await {
+                    Ok(()) => Ok(HttpResponse::Accepted().finish()),
+                    Err(e) => report_error(
+                        &req,
+                        Problem::new(StatusCode::BAD_GATEWAY, e.to_string()).into(),
+                        encoding,
+                        correlation_id.as_deref(),
+                        span.as_ref().map(|s| s.span_context()),
+                    ),
+                },
+                None => write_cloud_event(output, response_encoding(&req, encoding.as_ref())),
+            }
+        }
+        Err(e) => {
+            if let Some((queue, events)) = dead_letters {
+                record_failure(&queue, events, &e).await;
+            }
+            report_error(
+                &req,
+                e,
+                encoding,
+                correlation_id.as_deref(),
+                span.as_ref().map(|s| s.span_context()),
+            )
+        }
+    };
+
+    if let (Some((dedup, key)), Ok(res)) = (dedup, &result) {
+        dedup.put(key, res).await;
+    }
+
+    if let Some(mut span) = span {
+        let status = status(&result);
+        span.set_attribute(KeyValue::new(
+            "http.status_code",
+            i64::from(status.as_u16()),
+        ));
+        if status.is_server_error() {
+            span.set_status(SpanStatus::Error, String::new());
+        }
+        span.end();
+    }
+
+    result
+}
+
+/// Invokes the function catching its panics, which are reported as
+/// `500 Internal Server Error` without taking down the worker.
+async fn call_function<F, Fut>(
+    function: F,
+    req: HttpRequest,
+    events: Vec<Event>,
+    metrics: Option<&Data<Metrics>>,
+) -> Result<Vec<Event>, actix_web::Error>
+where
+    F: FnOnce(HttpRequest, Vec<Event>) -> Fut,
+    Fut: Future<Output = Result<Vec<Event>, actix_web::Error>>,
+{
+    let invocation = AssertUnwindSafe(async move { function(req, events).await });
+    match invocation.catch_unwind().await {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown cause"));
+            log::error!("FaaS Runtime: Function panicked: {}", message);
+            if let Some(metrics) = metrics {
+                metrics.panic();
+            }
+
+            Err(Problem::new(StatusCode::INTERNAL_SERVER_ERROR, "The function panicked").into())
+        }
+    }
+}
+
+/// Waits for the concurrency limits, if any. The invocation can proceed
+/// while the returned permits are held.
+async fn acquire_permits(
+    req: &HttpRequest,
+    event_type: Option<&str>,
+    metrics: Option<&Data<Metrics>>,
+) -> Result<Vec<Permit>, actix_web::Error> {
+    let limiter = match req.app_data::<Data<ConcurrencyLimiter>>() {
+        Some(limiter) => limiter.clone(),
+        None => return Ok(vec![]),
+    };
+    limiter.acquire(event_type).await.map_err(|rejection| {
+        if let Some(metrics) = metrics {
+            metrics.rejection(rejection.label());
+        }
+        rejection.to_problem(limiter.retry_after_seconds()).into()
+    })
+}
+
+/// Records the input events of an invocation failed with an error the caller
+/// shouldn't retry, otherwise they'd be lost.
+async fn record_failure(queue: &DeadLetterQueue, events: Vec<Event>, e: &actix_web::Error) {
+    let problem = Problem::of(e);
+    if problem.retryable {
+        return;
+    }
+    for event in events {
+        queue
+            .record(DeadLetter {
+                event,
+                stage: Stage::Function,
+                error: problem.detail.clone(),
+                attempts: 1,
+            })
+            .await;
+    }
+}
+
+fn deadline_exceeded(metrics: Option<&Data<Metrics>>) -> actix_web::Error {
+    if let Some(metrics) = metrics {
+        metrics.timeout();
+    }
+    Problem::new(
+        StatusCode::GATEWAY_TIMEOUT,
+        "The function didn't complete before the deadline",
+    )
+    .into()
+}
+
+/// Replies with an error event, if error events are enabled.
+fn report_error(
+    req: &HttpRequest,
+    e: actix_web::Error,
+    encoding: Option<Encoding>,
+    correlation_id: Option<&str>,
+    span_context: Option<&SpanContext>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let error_events = match req.app_data::<Data<ErrorEvents>>() {
+        Some(error_events) => error_events,
+        None => return Err(e),
+    };
+
+    let problem = Problem::of(&e);
+    let mut events = vec![error_events.to_event(&problem, correlation_id)];
+    if let Some(span_context) = span_context {
+        trace::inject(&mut events, span_context);
+    }
+    let encoding = encoding.unwrap_or_else(|| request_encoding(req));
+    let mut res = write_cloud_event(events, response_encoding(req, Some(&encoding)))?;
+    *res.status_mut() = ResponseError::status_code(&problem);
+    if let Some(retry_after) = problem.retry_after {
+        res.headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    Ok(res)
+}
+
+fn status(result: &Result<HttpResponse, actix_web::Error>) -> StatusCode {
+    match result {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    }
+}
+
+fn header_value<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Encoding of the response, by precedence: the one preferred by the
+/// `Accept` header, the one of the function, the one of the runtime and the
+/// one of the request.
+fn response_encoding(req: &HttpRequest, request_encoding: Option<&Encoding>) -> ResponseEncoding {
+    header_value(req, "accept")
+        .and_then(response_writer::negotiate)
+        .or_else(|| req.extensions().get::<ResponseEncoding>().copied())
+        .or_else(|| {
+            req.app_data::<Data<ResponseEncoding>>()
+                .map(|e| *e.get_ref())
+        })
+        .or_else(|| request_encoding.map(ResponseEncoding::from))
+        .unwrap_or_default()
+}
+
+fn request_encoding(req: &HttpRequest) -> Encoding {
+    let content_type = header_value(req, "content-type").unwrap_or("");
+    if content_type.contains("application/cloudevents-batch+json") {
+        Encoding::BATCH
+    } else if content_type.contains("application/cloudevents+json") {
+        Encoding::STRUCTURED
+    } else {
+        Encoding::BINARY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::DEADLINE_HEADER;
+    use crate::error::PROBLEM_CONTENT_TYPE;
+    use crate::trace::InMemoryExporter;
+    use actix_web::dev::Service;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{web, App, Route};
+    use chrono::Utc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Request with an event in binary encoding, with no payload.
+    fn event_request(path: &str, id: &str) -> TestRequest {
+        TestRequest::post()
+            .uri(path)
+            .header("ce-id", id)
+            .header("ce-source", "/shop")
+            .header("ce-type", "dev.knative.order")
+            .header("ce-specversion", "1.0")
+    }
+
+    /// Function echoing the input events, counting its invocations.
+    fn echo(calls: Arc<AtomicUsize>) -> Route {
+        web::post().to(move |req: HttpRequest, payload: Payload| {
+            let calls = calls.clone();
+            handle(req, payload, move |_, events| async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(events)
+            })
+        })
+    }
+
+    /// Function echoing the input events after `delay`.
+    fn slow(delay: Duration) -> Route {
+        web::post().to(move |req: HttpRequest, payload: Payload| {
+            handle(req, payload, move |_, events| async move {
+                actix_rt::time::delay_for(delay).await;
+                Ok(events)
+            })
+        })
+    }
+
+    #[actix_rt::test]
+    async fn test_invocation_timeout() {
+        let mut app = test::init_service(
+            App::new()
+                .data(InvocationTimeout(Duration::from_millis(50)))
+                .route("/", slow(Duration::from_secs(5))),
+        )
+        .await;
+
+        let res = test::call_service(&mut app, event_request("/", "1").to_request()).await;
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            PROBLEM_CONTENT_TYPE
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_request_deadline() {
+        let mut app = test::init_service(App::new().route("/", slow(Duration::from_secs(5)))).await;
+        let deadline = Utc::now() + chrono::Duration::milliseconds(50);
+
+        let req = event_request("/", "1")
+            .header(DEADLINE_HEADER, deadline.to_rfc3339())
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[actix_rt::test]
+    async fn test_queue_time_does_not_count_against_timeout() {
+        let mut app = test::init_service(
+            App::new()
+                .data(InvocationTimeout(Duration::from_millis(300)))
+                .data(ConcurrencyLimiter::new().limit(1).queue_size(1))
+                .route("/", slow(Duration::from_millis(200))),
+        )
+        .await;
+
+        // The second invocation waits for the first one, then runs in time
+        let first = app.call(event_request("/", "1").to_request());
+        let second = app.call(event_request("/", "2").to_request());
+        let (first, second) = futures::future::join(first, second).await;
+        assert_eq!(first.unwrap().status(), StatusCode::OK);
+        assert_eq!(second.unwrap().status(), StatusCode::OK);
+    }
+
+    fn panic_sync(
+        _: HttpRequest,
+        _: Vec<Event>,
+    ) -> futures::future::Ready<Result<Vec<Event>, actix_web::Error>> {
+        panic!("Sync function failed")
+    }
+
+    async fn panic_async(_: HttpRequest, _: Vec<Event>) -> Result<Vec<Event>, actix_web::Error> {
+        panic!("Async function failed")
+    }
+
+    fn panics(metrics: &Metrics) -> f64 {
+        metrics
+            .registry()
+            .gather()
+            .iter()
+            .find(|family| family.get_name() == "faas_panics_total")
+            .map(|family| family.get_metric()[0].get_counter().get_value())
+            .unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_panics_are_reported() {
+        let metrics = Metrics::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut app = test::init_service(
+            App::new()
+                .data(metrics.clone())
+                .route(
+                    "/sync",
+                    web::post()
+                        .to(|req: HttpRequest, payload: Payload| handle(req, payload, panic_sync)),
+                )
+                .route(
+                    "/async",
+                    web::post()
+                        .to(|req: HttpRequest, payload: Payload| handle(req, payload, panic_async)),
+                )
+                .route("/echo", echo(calls.clone())),
+        )
+        .await;
+
+        for path in &["/sync", "/async"] {
+            let res = test::call_service(&mut app, event_request(path, "1").to_request()).await;
+            assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(
+                res.headers().get("content-type").unwrap(),
+                PROBLEM_CONTENT_TYPE
+            );
+        }
+        assert_eq!(panics(&metrics), 2.0);
+
+        // The worker keeps serving after the panics
+        let res = test::call_service(&mut app, event_request("/echo", "2").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_duplicates_are_deduplicated_by_route() {
+        let orders = Arc::new(AtomicUsize::new(0));
+        let payments = Arc::new(AtomicUsize::new(0));
+        let mut app = test::init_service(
+            App::new()
+                .data(Deduplicator::default())
+                .route("/orders", echo(orders.clone()))
+                .route("/payments", echo(payments.clone())),
+        )
+        .await;
+
+        for path in &["/orders", "/orders", "/payments"] {
+            let res = test::call_service(&mut app, event_request(path, "1").to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers().get("ce-id").unwrap(), "1");
+        }
+        assert_eq!(orders.load(Ordering::SeqCst), 1);
+        assert_eq!(payments.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_runtime_response_encoding() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut app = test::init_service(
+            App::new()
+                .data(ResponseEncoding::Structured)
+                .route("/", echo(calls)),
+        )
+        .await;
+
+        // The runtime encoding wins over the one of the request
+        let res = test::call_service(&mut app, event_request("/", "1").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/cloudevents+json"
+        );
+        assert!(res.headers().get("ce-id").is_none());
+
+        // The caller can still ask for another one
+        let req = event_request("/", "2")
+            .header("accept", "application/cloudevents-batch+json")
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/cloudevents-batch+json"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_binary_trace_context_is_continued() {
+        let exporter = InMemoryExporter::default();
+        let mut app = test::init_service(
+            App::new()
+                .data(Tracer::new(exporter.clone()))
+                .route("/", echo(Arc::new(AtomicUsize::new(0)))),
+        )
+        .await;
+
+        let req = event_request("/", "1")
+            .header(
+                "ce-traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            )
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let traceparent = res.headers().get(trace::TRACEPARENT).unwrap();
+        assert!(traceparent
+            .to_str()
+            .unwrap()
+            .starts_with("00-0af7651916cd43dd8448eb211c80319c-"));
+
+        // The spans are exported in the background
+        let mut spans = exporter.spans();
+        for _ in 0..100 {
+            if !spans.is_empty() {
+                break;
+            }
+            actix_rt::time::delay_for(Duration::from_millis(10)).await;
+            spans = exporter.spans();
+        }
+        assert_eq!(spans.len(), 1);
+        assert_eq!(
+            spans[0].span_context.trace_id().to_string(),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+        assert_eq!(spans[0].parent_span_id.to_string(), "b7ad6b7169203331");
+    }
+}
@@ -1,68 +1,86 @@
 extern crate futures;
 extern crate serde_json;
 
+mod activation;
+pub mod config;
+pub mod context;
+pub mod dead_letter;
+pub mod dedup;
+pub mod emitter;
+pub mod error;
+mod h2c;
+pub mod health;
+pub mod invocation;
+pub mod limit;
+pub mod logging;
+pub mod metrics;
+pub mod payload;
+mod protobuf;
 pub mod request_reader;
 pub mod response_writer;
+pub mod runtime;
+mod shutdown;
+pub mod sink;
+pub mod tls;
+pub mod trace;
+mod transform;
+pub mod uds;
+
+pub use config::RuntimeConfig;
+pub use context::Context;
+pub use emitter::Emitter;
+pub use error::FunctionError;
+pub use logging::Logger;
+pub use runtime::{Middleware, Runtime};
 
 use actix_web::{guard, Route};
-use std::env;
-use std::net::SocketAddr;
 
-const PORT_ENV: &str = "PORT";
-const UNIX_DOMAIN_SOCKET_ENV: &str = "UNIX_DOMAIN_SOCKET";
-const LOG_ENV: &str = "FAAS_LOG";
+const DEFAULT_PATH: &str = "/*";
 
-fn configure_logging() {
-    let enable: bool = env::var(LOG_ENV)
-        .ok()
-        .and_then(|s| s.parse::<bool>().ok())
-        .unwrap_or(false);
-    if enable {
-        ::std::env::set_var("RUST_LOG", "actix_server=info,actix_web=info");
-        env_logger::init();
-    }
-}
+type RouteModFn = fn(Route) -> Route;
 
-fn get_bind_address() -> SocketAddr {
-    let port: u16 = env::var(PORT_ENV)
-        .ok()
-        .and_then(|s| s.parse::<u16>().ok())
-        .unwrap_or(8080);
+/// Generates the `main` serving a set of functions annotated with
+/// `#[faas_function(path = "...")]`, each one mounted on its own path.
+///
+/// ```ignore
+/// faas_rust::faas_main!(orders, payments);
+/// ```
+#[macro_export]
+macro_rules! faas_main {
+    ($($function:ident),+ $(,)?) => {
+        #[actix_rt::main]
+        async fn main() -> std::io::Result<()> {
+            $crate::start_functions_runtime(vec![
+                $(($function::PATH, $function::route as fn(actix_web::Route) -> actix_web::Route)),+
+            ])
+            .await
+        }
+    };
+}
 
-    ([0, 0, 0, 0], port).into()
+/// Creates the route matching the function invocations, to be completed with
+/// the function handler.
+pub fn function_route() -> Route {
+    actix_web::web::route().guard(guard::Any(guard::Get()).or(guard::Post()))
 }
 
 pub async fn start_runtime(route_mod_fn: fn(Route) -> Route) -> std::io::Result<()> {
-    configure_logging();
+    start_functions_runtime(vec![(DEFAULT_PATH, route_mod_fn)]).await
+}
 
-    let server = actix_web::HttpServer::new(move || {
-        actix_web::App::new()
-            .wrap(actix_web::middleware::Logger::default())
-            .route(
-                "/*",
-                route_mod_fn(
-                    actix_web::web::route().guard(guard::Any(guard::Get()).or(guard::Post())),
-                ),
-            )
-    });
+/// Starts the runtime serving every function on its path. Routes are matched
+/// in order, so a catch-all path like `/*` should be the last one.
+pub async fn start_functions_runtime(
+    functions: Vec<(&'static str, RouteModFn)>,
+) -> std::io::Result<()> {
+    let config = RuntimeConfig::load()?;
 
-    if let Some(uds_address) = env::var(UNIX_DOMAIN_SOCKET_ENV).ok() {
-        println!(
-            "FaaS Runtime: Starting server listening Unix Domain Socket {}",
-            uds_address
-        );
-        server
-            .bind_uds(&uds_address)
-            .expect(format!("Cannot bind uds {}", uds_address).as_ref())
-            .run()
-            .await
-    } else {
-        let addr: std::net::SocketAddr = get_bind_address();
-        println!("Starting server listening {}", addr);
-        server
-            .bind(addr)
-            .expect(format!("Cannot bind address {}", addr).as_ref())
-            .run()
-            .await
-    }
+    functions
+        .into_iter()
+        .fold(
+            Runtime::from_config(config),
+            |runtime, (path, route_mod_fn)| runtime.function(path, route_mod_fn),
+        )
+        .run()
+        .await
 }
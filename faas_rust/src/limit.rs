@@ -0,0 +1,265 @@
+use crate::error::Problem;
+use actix_web::http::StatusCode;
+use futures::channel::oneshot;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_RETRY_AFTER: u64 = 1;
+
+/// Limits the invocations in progress, globally and per event type.
+///
+/// When a limit is reached the invocations wait in a bounded queue. If the
+/// queue is full they're rejected with `429 Too Many Requests`, if they wait
+/// longer than the queue timeout with `503 Service Unavailable`. Both
+/// responses include the `Retry-After` header.
+///
+/// The limiter is shared by all the workers.
+#[derive(Clone)]
+pub struct ConcurrencyLimiter {
+    global: Option<Arc<Limit>>,
+    per_type: HashMap<String, Arc<Limit>>,
+    queue_size: usize,
+    queue_timeout: Option<Duration>,
+    retry_after: u64,
+}
+
+impl Default for ConcurrencyLimiter {
+    fn default() -> Self {
+        ConcurrencyLimiter {
+            global: None,
+            per_type: HashMap::new(),
+            queue_size: 0,
+            queue_timeout: None,
+            retry_after: DEFAULT_RETRY_AFTER,
+        }
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new() -> ConcurrencyLimiter {
+        ConcurrencyLimiter::default()
+    }
+
+    /// Maximum invocations in progress.
+    pub fn limit(mut self, max: usize) -> Self {
+        self.global = Some(Arc::new(Limit::new(max)));
+        self
+    }
+
+    /// Maximum invocations in progress for events of `event_type`.
+    pub fn event_type_limit<S: Into<String>>(mut self, event_type: S, max: usize) -> Self {
+        self.per_type
+            .insert(event_type.into(), Arc::new(Limit::new(max)));
+        self
+    }
+
+    /// Invocations which can wait for each limit. Defaults to 0.
+    pub fn queue_size(mut self, size: usize) -> Self {
+        self.queue_size = size;
+        self
+    }
+
+    /// Maximum time an invocation can wait in the queue.
+    pub fn queue_timeout(mut self, timeout: Duration) -> Self {
+        self.queue_timeout = Some(timeout);
+        self
+    }
+
+    /// Seconds in the `Retry-After` header of the rejected invocations.
+    /// Defaults to 1 second.
+    pub fn retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = seconds;
+        self
+    }
+
+    /// Waits until the invocation can proceed. The permits must be held
+    /// until the invocation completes.
+    ///
+    /// The limit of the event type is acquired first, so the invocations
+    /// waiting for it don't hold global slots the other event types could
+    /// use.
+    pub(crate) async fn acquire(&self, event_type: Option<&str>) -> Result<Vec<Permit>, Rejection> {
+        let mut permits = Vec::with_capacity(2);
+        if let Some(limit) = event_type.and_then(|t| self.per_type.get(t)) {
+            permits.push(self.acquire_limit(limit).await?);
+        }
+        if let Some(global) = &self.global {
+            permits.push(self.acquire_limit(global).await?);
+        }
+        Ok(permits)
+    }
+
+    async fn acquire_limit(&self, limit: &Arc<Limit>) -> Result<Permit, Rejection> {
+        let receiver = {
+            let mut state = limit.state.lock().unwrap();
+            if state.in_flight < limit.max {
+                state.in_flight += 1;
+                return Ok(Permit(Some(limit.clone())));
+            }
+            state.waiters.retain(|w| !w.is_canceled());
+            if state.waiters.len() >= self.queue_size {
+                return Err(Rejection::QueueFull);
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.waiters.push_back(sender);
+            receiver
+        };
+
+        match self.queue_timeout {
+            Some(timeout) => match actix_rt::time::timeout(timeout, receiver).await {
+                Ok(Ok(permit)) => Ok(permit),
+                _ => Err(Rejection::QueueTimeout),
+            },
+            None => receiver.await.map_err(|_| Rejection::QueueTimeout),
+        }
+    }
+
+    pub(crate) fn retry_after_seconds(&self) -> u64 {
+        self.retry_after
+    }
+}
+
+struct Limit {
+    max: usize,
+    state: Mutex<LimitState>,
+}
+
+struct LimitState {
+    in_flight: usize,
+    waiters: VecDeque<oneshot::Sender<Permit>>,
+}
+
+impl Limit {
+    fn new(max: usize) -> Limit {
+        Limit {
+            max,
+            state: Mutex::new(LimitState {
+                in_flight: 0,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+}
+
+/// Slot of an invocation in progress. When dropped, it's handed over to the
+/// first invocation waiting in the queue, if any.
+pub(crate) struct Permit(Option<Arc<Limit>>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let limit = match self.0.take() {
+            Some(limit) => limit,
+            None => return,
+        };
+        let mut state = limit.state.lock().unwrap();
+        while let Some(waiter) = state.waiters.pop_front() {
+            match waiter.send(Permit(Some(limit.clone()))) {
+                Ok(()) => return,
+                // The invocation stopped waiting
+                Err(mut permit) => {
+                    permit.0.take();
+                }
+            }
+        }
+        state.in_flight -= 1;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Rejection {
+    QueueFull,
+    QueueTimeout,
+}
+
+impl Rejection {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Rejection::QueueFull => "queue_full",
+            Rejection::QueueTimeout => "queue_timeout",
+        }
+    }
+
+    pub(crate) fn to_problem(self, retry_after: u64) -> Problem {
+        let problem = match self {
+            Rejection::QueueFull => Problem::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many invocations in progress",
+            ),
+            Rejection::QueueTimeout => Problem::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Timed out waiting for the invocations in progress",
+            ),
+        };
+        problem.retry_after(retry_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_rejects_when_queue_is_full() {
+        let limiter = ConcurrencyLimiter::new().limit(1);
+
+        block_on(async {
+            let permits = limiter.acquire(None).await.unwrap();
+            assert_eq!(
+                limiter.acquire(None).await.err(),
+                Some(Rejection::QueueFull)
+            );
+
+            drop(permits);
+            assert!(limiter.acquire(None).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_queued_invocation_gets_released_permit() {
+        let limiter = ConcurrencyLimiter::new()
+            .event_type_limit("dev.knative.example", 1)
+            .queue_size(1);
+
+        block_on(async {
+            let permits = limiter.acquire(Some("dev.knative.example")).await.unwrap();
+            assert_eq!(permits.len(), 1);
+            assert!(limiter.acquire(Some("dev.knative.other")).await.is_ok());
+
+            let mut waiting = Box::pin(limiter.acquire(Some("dev.knative.example")));
+            assert!(futures::poll!(&mut waiting).is_pending());
+            assert_eq!(
+                limiter.acquire(Some("dev.knative.example")).await.err(),
+                Some(Rejection::QueueFull)
+            );
+
+            drop(permits);
+            assert!(waiting.await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_saturated_event_type_does_not_starve_others() {
+        let limiter = ConcurrencyLimiter::new()
+            .limit(2)
+            .event_type_limit("dev.knative.order", 1)
+            .queue_size(2);
+
+        block_on(async {
+            let order = limiter.acquire(Some("dev.knative.order")).await.unwrap();
+            assert_eq!(order.len(), 2);
+            let mut waiting = Box::pin(limiter.acquire(Some("dev.knative.order")));
+            assert!(futures::poll!(&mut waiting).is_pending());
+
+            let mut payment = Box::pin(limiter.acquire(Some("dev.knative.payment")));
+            match futures::poll!(&mut payment) {
+                std::task::Poll::Ready(permits) => assert!(permits.is_ok()),
+                std::task::Poll::Pending => panic!("The other event type is waiting"),
+            }
+
+            drop(order);
+            assert!(waiting.await.is_ok());
+        });
+    }
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+const ACCESS_LOG_FILTER: &str = "info";
+const FUNCTION_TARGET: &str = "function";
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, including the attributes of the event in process
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Invalid log format {}, expecting text or json", s)),
+        }
+    }
+}
+
+/// Attributes of the invocation added to every log entry in JSON format.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct LogContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+thread_local! {
+    static CURRENT_CONTEXT: RefCell<Option<LogContext>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous context when dropped, even if the scope panics.
+struct ContextGuard(Option<LogContext>);
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT_CONTEXT.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+fn with_context<R, F: FnOnce() -> R>(ctx: &LogContext, f: F) -> R {
+    let previous = CURRENT_CONTEXT.with(|c| c.replace(Some(ctx.clone())));
+    let _guard = ContextGuard(previous);
+    f()
+}
+
+/// Future running `inner` with `ctx` as the current log context.
+pub(crate) struct Scoped<F> {
+    ctx: LogContext,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let inner = &mut this.inner;
+        with_context(&this.ctx, || inner.as_mut().poll(cx))
+    }
+}
+
+pub(crate) fn scope<F: Future>(ctx: LogContext, inner: F) -> Scoped<F> {
+    Scoped {
+        ctx,
+        inner: Box::pin(inner),
+    }
+}
+
+/// Logger available to the functions, declaring an argument of type `Logger`.
+/// The entries are logged with target `function`.
+#[derive(Clone, Debug, Default)]
+pub struct Logger {
+    ctx: LogContext,
+}
+
+impl Logger {
+    /// Logger with the context of the invocation in progress.
+    pub fn current() -> Logger {
+        Logger {
+            ctx: CURRENT_CONTEXT
+                .with(|c| c.borrow().clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn context(&self) -> &LogContext {
+        &self.ctx
+    }
+
+    pub fn log<M: fmt::Display>(&self, level: log::Level, message: M) {
+        with_context(
+            &self.ctx,
+            || log::log!(target: FUNCTION_TARGET, level, "{}", message),
+        )
+    }
+
+    pub fn error<M: fmt::Display>(&self, message: M) {
+        self.log(log::Level::Error, message)
+    }
+
+    pub fn warn<M: fmt::Display>(&self, message: M) {
+        self.log(log::Level::Warn, message)
+    }
+
+    pub fn info<M: fmt::Display>(&self, message: M) {
+        self.log(log::Level::Info, message)
+    }
+
+    pub fn debug<M: fmt::Display>(&self, message: M) {
+        self.log(log::Level::Debug, message)
+    }
+}
+
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    timestamp: String,
+    level: String,
+    target: &'a str,
+    message: String,
+    #[serde(flatten)]
+    ctx: Option<LogContext>,
+}
+
+fn format_json(record: &log::Record, ctx: Option<LogContext>) -> String {
+    let entry = JsonEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        level: record.level().to_string(),
+        target: record.target(),
+        message: record.args().to_string(),
+        ctx,
+    };
+    serde_json::to_string(&entry).unwrap_or_default()
+}
+
+/// Filter of the logger, if any. Like the access log, logging is opt-in: the
+/// runtime is silent unless `access_log` is enabled or a `filter` is provided.
+fn log_filter(access_log: bool, filter: Option<&str>) -> Option<&str> {
+    match filter {
+        Some(filter) => Some(filter),
+        None if access_log => Some(ACCESS_LOG_FILTER),
+        None => None,
+    }
+}
+
+/// Initializes the logger, unless logging is disabled.
+pub(crate) fn init(access_log: bool, format: LogFormat, filter: Option<&str>) {
+    let filter = match log_filter(access_log, filter) {
+        Some(filter) => filter,
+        None => return,
+    };
+
+    let mut builder = env_logger::Builder::new();
+    builder.parse_filters(filter);
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let ctx = CURRENT_CONTEXT.with(|c| c.borrow().clone());
+            writeln!(buf, "{}", format_json(record, ctx))
+        });
+    }
+    // The logger can be already initialized when the runtime is started twice
+    let _ = builder.try_init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_json_with_context() {
+        let ctx = LogContext {
+            event_id: Some(String::from("1")),
+            event_type: Some(String::from("dev.knative.example")),
+            ..LogContext::default()
+        };
+        let line = format_json(
+            &log::Record::builder()
+                .args(format_args!("hello"))
+                .level(log::Level::Info)
+                .target("function")
+                .build(),
+            Some(ctx),
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["message"], "hello");
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "function");
+        assert_eq!(json["event_id"], "1");
+        assert_eq!(json["event_type"], "dev.knative.example");
+        assert!(json.get("event_source").is_none());
+    }
+
+    #[test]
+    fn test_scope_sets_current_context() {
+        let ctx = LogContext {
+            event_id: Some(String::from("1")),
+            ..LogContext::default()
+        };
+        let logger = futures::executor::block_on(scope(ctx.clone(), async { Logger::current() }));
+
+        assert_eq!(logger.context(), &ctx);
+        assert_eq!(Logger::current().context(), &LogContext::default());
+    }
+
+    #[test]
+    fn test_logging_is_opt_in() {
+        assert_eq!(log_filter(false, None), None);
+        assert_eq!(log_filter(true, None), Some(ACCESS_LOG_FILTER));
+        assert_eq!(log_filter(false, Some("warn")), Some("warn"));
+        assert_eq!(log_filter(true, Some("warn")), Some("warn"));
+    }
+}
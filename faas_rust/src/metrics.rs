@@ -0,0 +1,247 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+/// Label of the event types which are not in the allowlist.
+pub const OTHER_EVENT_TYPE: &str = "other";
+
+/// Metrics of the function invocations, exposed in the Prometheus text format.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: Histogram,
+    in_flight: IntGauge,
+    parse_failures: IntCounterVec,
+    events: IntCounterVec,
+    event_types: Arc<HashSet<String>>,
+    panics: IntCounter,
+    timeouts: IntCounter,
+    rejections: IntCounterVec,
+    duplicates: IntCounter,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let registry = Registry::new();
+
+        let requests = IntCounterVec::new(
+            Opts::new("faas_requests_total", "Function invocations by status code"),
+            &["status"],
+        )
+        .unwrap();
+        let latency = Histogram::with_opts(HistogramOpts::new(
+            "faas_invocation_duration_seconds",
+            "Function invocations latency",
+        ))
+        .unwrap();
+        let in_flight = IntGauge::new(
+            "faas_invocations_in_flight",
+            "Function invocations in progress",
+        )
+        .unwrap();
+        let parse_failures = IntCounterVec::new(
+            Opts::new(
+                "faas_parse_failures_total",
+                "Requests which cannot be parsed as cloud events, by encoding",
+            ),
+            &["encoding"],
+        )
+        .unwrap();
+        let events = IntCounterVec::new(
+            Opts::new("faas_events_total", "Received cloud events by type"),
+            &["type"],
+        )
+        .unwrap();
+        let panics =
+            IntCounter::new("faas_panics_total", "Function invocations which panicked").unwrap();
+        let timeouts = IntCounter::new(
+            "faas_timeouts_total",
+            "Function invocations cancelled at the deadline",
+        )
+        .unwrap();
+
+        let rejections = IntCounterVec::new(
+            Opts::new(
+                "faas_rejections_total",
+                "Function invocations rejected by the concurrency limits, by reason",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        let duplicates = IntCounter::new(
+            "faas_duplicates_total",
+            "Duplicate events replied with the cached response",
+        )
+        .unwrap();
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        registry.register(Box::new(in_flight.clone())).unwrap();
+        registry.register(Box::new(parse_failures.clone())).unwrap();
+        registry.register(Box::new(events.clone())).unwrap();
+        registry.register(Box::new(panics.clone())).unwrap();
+        registry.register(Box::new(timeouts.clone())).unwrap();
+        registry.register(Box::new(rejections.clone())).unwrap();
+        registry.register(Box::new(duplicates.clone())).unwrap();
+
+        Metrics {
+            registry,
+            requests,
+            latency,
+            in_flight,
+            parse_failures,
+            events,
+            event_types: Arc::new(HashSet::new()),
+            panics,
+            timeouts,
+            rejections,
+            duplicates,
+        }
+    }
+
+    /// Event types counted with their own label, the others are counted as
+    /// `other` to bound the cardinality of the metrics.
+    pub fn event_types<I, S>(mut self, event_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.event_types = Arc::new(event_types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Registry where additional metrics can be registered.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    pub(crate) fn in_flight(&self) -> InFlightGuard {
+        self.in_flight.inc();
+        InFlightGuard(self.in_flight.clone())
+    }
+
+    pub(crate) fn observe_request(&self, status: StatusCode, latency: Duration) {
+        self.requests.with_label_values(&[status.as_str()]).inc();
+        self.latency.observe(latency.as_secs_f64());
+    }
+
+    pub(crate) fn parse_failure(&self, encoding: &str) {
+        self.parse_failures.with_label_values(&[encoding]).inc();
+    }
+
+    pub(crate) fn event(&self, event_type: &str) {
+        let label = if self.event_types.contains(event_type) {
+            event_type
+        } else {
+            OTHER_EVENT_TYPE
+        };
+        self.events.with_label_values(&[label]).inc();
+    }
+
+    pub(crate) fn panic(&self) {
+        self.panics.inc();
+    }
+
+    pub(crate) fn timeout(&self) {
+        self.timeouts.inc();
+    }
+
+    pub(crate) fn rejection(&self, reason: &str) {
+        self.rejections.with_label_values(&[reason]).inc();
+    }
+
+    pub(crate) fn duplicate(&self) {
+        self.duplicates.inc();
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// Decrements the in-flight gauge when the invocation completes.
+pub(crate) struct InFlightGuard(IntGauge);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+pub(crate) fn configure(cfg: &mut web::ServiceConfig, metrics_path: &str, metrics: Metrics) {
+    cfg.data(metrics).route(metrics_path, web::get().to(export));
+}
+
+async fn export(metrics: web::Data<Metrics>) -> HttpResponse {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    match encoder.encode(&metrics.registry.gather(), &mut buffer) {
+        Ok(()) => HttpResponse::Ok()
+            .content_type(encoder.format_type())
+            .body(buffer),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invocation::handle;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::web::Payload;
+    use actix_web::{App, HttpRequest};
+
+    fn event_request(event_type: &str) -> TestRequest {
+        TestRequest::post()
+            .uri("/")
+            .header("ce-id", "1")
+            .header("ce-source", "/shop")
+            .header("ce-type", event_type)
+            .header("ce-specversion", "1.0")
+    }
+
+    #[actix_rt::test]
+    async fn test_scrape_invocation_metrics() {
+        let metrics = Metrics::new().event_types(vec!["dev.knative.order"]);
+        let mut app = test::init_service(
+            App::new()
+                .configure(|cfg| configure(cfg, DEFAULT_METRICS_PATH, metrics))
+                .route(
+                    "/",
+                    web::post().to(|req: HttpRequest, payload: Payload| {
+                        handle(req, payload, |_, events| async { Ok(events) })
+                    }),
+                ),
+        )
+        .await;
+
+        for event_type in &[
+            "dev.knative.order",
+            "dev.knative.payment",
+            "dev.knative.refund",
+        ] {
+            let res = test::call_service(&mut app, event_request(event_type).to_request()).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        let req = TestRequest::get().uri(DEFAULT_METRICS_PATH).to_request();
+        let body = test::read_response(&mut app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains("faas_requests_total{status=\"200\"} 3"));
+        assert!(body.contains("faas_events_total{type=\"dev.knative.order\"} 1"));
+        // The event types out of the allowlist share a single label
+        assert!(body.contains("faas_events_total{type=\"other\"} 2"));
+        assert!(!body.contains("dev.knative.payment"));
+        assert!(body.contains("faas_invocations_in_flight 0"));
+    }
+}
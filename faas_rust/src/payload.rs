@@ -0,0 +1,229 @@
+use crate::error::Problem;
+use actix_web::http::{header, StatusCode};
+use actix_web::web::{Bytes, BytesMut, Payload};
+use actix_web::HttpRequest;
+use cloudevent::http::Encoding;
+use futures::StreamExt;
+
+/// Default maximum size of the payloads, the same of actix.
+pub const DEFAULT_PAYLOAD_LIMIT: usize = 262_144;
+
+/// Maximum size in bytes of the request payload, by encoding of the events.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PayloadLimits {
+    pub binary: usize,
+    pub structured: usize,
+    pub batch: usize,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        PayloadLimits::all(DEFAULT_PAYLOAD_LIMIT)
+    }
+}
+
+impl PayloadLimits {
+    /// Same limit for every encoding.
+    pub fn all(bytes: usize) -> PayloadLimits {
+        PayloadLimits {
+            binary: bytes,
+            structured: bytes,
+            batch: bytes,
+        }
+    }
+
+    pub fn get(&self, encoding: &Encoding) -> usize {
+        match encoding {
+            Encoding::BINARY => self.binary,
+            Encoding::STRUCTURED => self.structured,
+            Encoding::BATCH => self.batch,
+        }
+    }
+
+    pub(crate) fn max(&self) -> usize {
+        self.binary.max(self.structured).max(self.batch)
+    }
+}
+
+/// Reads the request payload, failing with `413 Payload Too Large` as soon as
+/// it exceeds the limit of `encoding`.
+pub(crate) async fn read_body(
+    req: &HttpRequest,
+    mut payload: Payload,
+    encoding: &Encoding,
+    limits: &PayloadLimits,
+) -> Result<Bytes, actix_web::Error> {
+    let limit = limits.get(encoding);
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if let Some(length) = content_length {
+        if length > limit {
+            return Err(too_large(encoding, limit).into());
+        }
+    }
+
+    let mut body = BytesMut::with_capacity(content_length.unwrap_or(0));
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > limit {
+            return Err(too_large(encoding, limit).into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+fn too_large(encoding: &Encoding, limit: usize) -> Problem {
+    Problem::new(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!(
+            "The payload exceeds the limit of {} bytes for {} events",
+            limit,
+            encoding_label(encoding)
+        ),
+    )
+}
+
+pub(crate) fn encoding_label(encoding: &Encoding) -> &'static str {
+    match encoding {
+        Encoding::BINARY => "binary",
+        Encoding::STRUCTURED => "structured",
+        Encoding::BATCH => "batch",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PROBLEM_CONTENT_TYPE;
+    use crate::invocation;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{web, App};
+
+    fn limits() -> PayloadLimits {
+        PayloadLimits {
+            binary: 10,
+            structured: 100,
+            batch: 1000,
+        }
+    }
+
+    fn status(result: Result<Bytes, actix_web::Error>) -> StatusCode {
+        match result {
+            Ok(_) => StatusCode::OK,
+            Err(e) => e.as_response_error().status_code(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_limit_by_encoding() {
+        let body = vec![b'x'; 50];
+        for (encoding, expected) in [
+            (Encoding::BINARY, StatusCode::PAYLOAD_TOO_LARGE),
+            (Encoding::STRUCTURED, StatusCode::OK),
+            (Encoding::BATCH, StatusCode::OK),
+        ] {
+            let (req, payload) = TestRequest::default()
+                .set_payload(body.clone())
+                .to_http_parts();
+            let result = read_body(&req, Payload(payload), &encoding, &limits()).await;
+            assert_eq!(status(result), expected);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_content_length_checked_before_reading() {
+        let (req, payload) = TestRequest::default()
+            .header(header::CONTENT_LENGTH, "1000")
+            .set_payload("small")
+            .to_http_parts();
+        let result = read_body(&req, Payload(payload), &Encoding::STRUCTURED, &limits()).await;
+        assert_eq!(status(result), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_rt::test]
+    async fn test_streamed_payload_without_content_length() {
+        let (req, payload) = TestRequest::default()
+            .set_payload(vec![b'x'; 101])
+            .to_http_parts();
+        assert!(req.headers().get(header::CONTENT_LENGTH).is_none());
+        let result = read_body(&req, Payload(payload), &Encoding::STRUCTURED, &limits()).await;
+        assert_eq!(status(result), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let (req, payload) = TestRequest::default()
+            .set_payload(vec![b'x'; 100])
+            .to_http_parts();
+        let result = read_body(&req, Payload(payload), &Encoding::STRUCTURED, &limits()).await;
+        assert_eq!(result.unwrap().len(), 100);
+    }
+
+    #[actix_rt::test]
+    async fn test_payload_too_large_response() {
+        let mut app = test::init_service(App::new().data(limits()).route(
+            "/",
+            web::post().to(|req: HttpRequest, payload: Payload| {
+                invocation::handle(req, payload, |_, events| async move { Ok(events) })
+            }),
+        ))
+        .await;
+
+        let req = TestRequest::post()
+            .header("ce-id", "1")
+            .header("ce-source", "/shop")
+            .header("ce-type", "dev.knative.order")
+            .header("ce-specversion", "1.0")
+            .header("content-type", "text/plain")
+            .set_payload("more than ten bytes")
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            PROBLEM_CONTENT_TYPE
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_batch_payload_limit() {
+        let mut app = test::init_service(App::new().data(limits()).route(
+            "/",
+            web::post().to(|req: HttpRequest, payload: Payload| {
+                invocation::handle(req, payload, |_, events| async move { Ok(events) })
+            }),
+        ))
+        .await;
+        let batch = |size: usize| {
+            let events: Vec<_> = (0..size)
+                .map(|i| {
+                    serde_json::json!({
+                        "id": i.to_string(),
+                        "source": "/shop",
+                        "type": "dev.knative.order",
+                        "specversion": "1.0",
+                    })
+                })
+                .collect();
+            TestRequest::post()
+                .header("content-type", "application/cloudevents-batch+json")
+                .set_payload(serde_json::to_vec(&events).unwrap())
+                .to_request()
+        };
+
+        // Larger than the structured limit, within the batch one
+        let res = test::call_service(&mut app, batch(2)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/cloudevents-batch+json"
+        );
+        let events: Vec<serde_json::Value> =
+            serde_json::from_slice(&test::read_body(res).await).unwrap();
+        assert_eq!(events.len(), 2);
+
+        let res = test::call_service(&mut app, batch(20)).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
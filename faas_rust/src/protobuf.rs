@@ -0,0 +1,131 @@
+//! Encoder of the events in the CloudEvents protobuf format, as defined by
+//! the `cloudevents.proto` of the spec.
+use cloudevent::Event;
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_LEN: u64 = 2;
+
+// CloudEvent fields
+const ID: u64 = 1;
+const SOURCE: u64 = 2;
+const SPEC_VERSION: u64 = 3;
+const TYPE: u64 = 4;
+const ATTRIBUTES: u64 = 5;
+const BINARY_DATA: u64 = 6;
+
+// CloudEventAttributeValue fields
+const CE_STRING: u64 = 3;
+const CE_TIMESTAMP: u64 = 7;
+
+// CloudEventBatch fields
+const EVENTS: u64 = 1;
+
+pub(crate) fn encode_event(event: &Event) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_bytes(&mut buf, ID, event.id.as_bytes());
+    write_bytes(&mut buf, SOURCE, event.source.as_bytes());
+    write_bytes(
+        &mut buf,
+        SPEC_VERSION,
+        event.spec_version.to_string().as_bytes(),
+    );
+    write_bytes(&mut buf, TYPE, event.event_type.as_bytes());
+    if let Some(subject) = &event.subject {
+        write_attribute(&mut buf, "subject", &string_value(subject));
+    }
+    if let Some(time) = &event.time {
+        let mut timestamp = Vec::new();
+        write_varint_field(&mut timestamp, 1, time.timestamp() as u64);
+        write_varint_field(&mut timestamp, 2, u64::from(time.timestamp_subsec_nanos()));
+        let mut value = Vec::new();
+        write_bytes(&mut value, CE_TIMESTAMP, &timestamp);
+        write_attribute(&mut buf, "time", &value);
+    }
+    for (name, value) in &event.extensions {
+        write_attribute(&mut buf, name, &string_value(value));
+    }
+    if let Some(payload) = &event.payload {
+        write_attribute(
+            &mut buf,
+            "datacontenttype",
+            &string_value(&payload.content_type),
+        );
+        write_bytes(&mut buf, BINARY_DATA, &payload.data);
+    }
+    buf
+}
+
+pub(crate) fn encode_batch(events: &[Event]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for event in events {
+        write_bytes(&mut buf, EVENTS, &encode_event(event));
+    }
+    buf
+}
+
+fn string_value(value: &str) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_bytes(&mut buf, CE_STRING, value.as_bytes());
+    buf
+}
+
+/// Writes an entry of the attributes map.
+fn write_attribute(buf: &mut Vec<u8>, name: &str, value: &[u8]) {
+    let mut entry = Vec::new();
+    write_bytes(&mut entry, 1, name.as_bytes());
+    write_bytes(&mut entry, 2, value);
+    write_bytes(buf, ATTRIBUTES, &entry);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(buf, field << 3 | WIRE_LEN);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(buf, field << 3 | WIRE_VARINT);
+    write_varint(buf, value);
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudevent::Payload;
+
+    #[test]
+    fn test_encode_event_and_batch() {
+        let mut event = Event::new();
+        event.id = String::from("1");
+        event.source = String::from("/s");
+        event.event_type = String::from("t");
+        event.payload = Some(Payload {
+            content_type: String::from("text/plain"),
+            data: b"hi".to_vec(),
+        });
+
+        let mut expected = vec![
+            0x0a, 1, b'1', 0x12, 2, b'/', b's', 0x1a, 3, b'1', b'.', b'0',
+        ];
+        expected.extend_from_slice(&[0x22, 1, b't']);
+        // datacontenttype = { ce_string: "text/plain" }
+        expected.extend_from_slice(&[0x2a, 31, 0x0a, 15]);
+        expected.extend_from_slice(b"datacontenttype");
+        expected.extend_from_slice(&[0x12, 12, 0x1a, 10]);
+        expected.extend_from_slice(b"text/plain");
+        expected.extend_from_slice(&[0x32, 2, b'h', b'i']);
+        assert_eq!(encode_event(&event), expected);
+
+        let mut batch = vec![0x0a, expected.len() as u8];
+        batch.extend_from_slice(&expected);
+        assert_eq!(encode_batch(&[event]), batch);
+    }
+}
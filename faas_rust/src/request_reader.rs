@@ -35,8 +35,9 @@ macro_rules! unwrap_and_remove_header {
 
 // Possible cases:
 // 1. Content-type exists:
-// 1.1 If application/cloudevents+json -> parse structured
-// 1.2 If other -> parse binary
+// 1.1 If application/cloudevents-batch+json -> parse batch
+// 1.2 If application/cloudevents+json -> parse structured
+// 1.3 If other -> parse binary
 // 2. Content-type doesn't exist:
 // 2.1 If CE id header, then it's an empty payload cloud event -> parse binary
 // 2.2 If no CE header -> None
@@ -47,7 +48,18 @@ pub async fn read_cloud_event(
     let mut headers: HeaderMap = req.headers().clone();
 
     if let Ok(ct) = unwrap_and_remove_header!(headers, "content-type") {
-        if ct.contains("application/cloudevents+json") {
+        if ct.contains("application/cloudevents-batch+json") {
+            if payload.is_empty() {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "No payload provided but content type is {}",
+                    ct
+                )));
+            } else {
+                return parse_batch(payload)
+                    .await
+                    .map(|ce| Some((Encoding::BATCH, ce)));
+            }
+        } else if ct.contains("application/cloudevents+json") {
             // Payload at this point should not be none
             if payload.is_empty() {
                 return Err(actix_web::error::ErrorBadRequest(format!(
@@ -87,6 +99,11 @@ async fn parse_structured(payload: Bytes) -> Result<Event, actix_web::Error> {
         .map_err(|e| actix_web::error::ErrorBadRequest(format!("{}", e)))
 }
 
+async fn parse_batch(payload: Bytes) -> Result<Vec<Event>, actix_web::Error> {
+    serde_json::from_slice::<Vec<Event>>(&payload)
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("{}", e)))
+}
+
 async fn parse_binary(
     headers: HeaderMap,
     payload: Option<(String, Bytes)>,
@@ -128,8 +145,78 @@ fn read_ce_headers(mut headers: HeaderMap, ce: &mut Event) -> Result<(), actix_w
             })
             .ok();
 
-        //TODO extensions
+        // The remaining ce- headers are the extensions, e.g. ce-traceparent
+        for (name, value) in headers.iter() {
+            if let Some(extension) = name.as_str().strip_prefix("ce-") {
+                let value = value.to_str().map_err(|e| {
+                    actix_web::error::ErrorBadRequest(format!(
+                        "Error while parsing header {}: {}",
+                        name, e
+                    ))
+                })?;
+                ce.extensions
+                    .insert(String::from(extension), String::from(value));
+            }
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[actix_rt::test]
+    async fn test_binary_extensions() {
+        let req = TestRequest::post()
+            .header(CE_ID_HEADER, "1")
+            .header(CE_TYPE_HEADER, "order.created")
+            .header(CE_SPECVERSION_HEADER, "1.0")
+            .header(CE_SOURCE_HEADER, "/orders")
+            .header(
+                "ce-traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            )
+            .header("ce-tracestate", "congo=t61rcWkgMzE")
+            .header("ce-partitionkey", "42")
+            .header("x-request-id", "abc")
+            .to_http_request();
+
+        let (_, events) = read_cloud_event(req, Bytes::new()).await.unwrap().unwrap();
+        let extensions = &events[0].extensions;
+        assert_eq!(extensions.len(), 3);
+        assert_eq!(
+            extensions["traceparent"],
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+        );
+        assert_eq!(extensions["tracestate"], "congo=t61rcWkgMzE");
+        assert_eq!(extensions["partitionkey"], "42");
+    }
+
+    #[actix_rt::test]
+    async fn test_batch() {
+        let req = TestRequest::post()
+            .header("content-type", "application/cloudevents-batch+json")
+            .to_http_request();
+        let payload = Bytes::from_static(
+            br#"[
+                {"id": "1", "source": "/orders", "type": "order.created", "specversion": "1.0"},
+                {"id": "2", "source": "/orders", "type": "order.paid", "specversion": "1.0"}
+            ]"#,
+        );
+
+        let (encoding, events) = read_cloud_event(req, payload).await.unwrap().unwrap();
+        assert!(matches!(encoding, Encoding::BATCH));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].id, "1");
+        assert_eq!(events[1].event_type, "order.paid");
+
+        let req = TestRequest::post()
+            .header("content-type", "application/cloudevents-batch+json")
+            .to_http_request();
+        let payload = Bytes::from_static(br#"{"id": "1"}"#);
+        assert!(read_cloud_event(req, payload).await.is_err());
+    }
+}
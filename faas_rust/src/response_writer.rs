@@ -1,39 +1,146 @@
+use crate::protobuf;
+use crate::trace::{TRACEPARENT, TRACESTATE};
 use actix_web::HttpResponse;
 use cloudevent::http::*;
 use cloudevent::Event;
+use serde::Deserialize;
+use std::str::FromStr;
 
-const DEFAULT_ENCODING: Encoding = Encoding::BINARY;
+/// Encoding of the events in the response.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResponseEncoding {
+    #[default]
+    Binary,
+    Structured,
+    Batch,
+    Protobuf,
+    ProtobufBatch,
+}
+
+impl ResponseEncoding {
+    fn from_media_type(media_type: &str) -> Option<ResponseEncoding> {
+        match media_type {
+            "application/cloudevents+json" => Some(ResponseEncoding::Structured),
+            "application/cloudevents-batch+json" => Some(ResponseEncoding::Batch),
+            "application/cloudevents+protobuf" => Some(ResponseEncoding::Protobuf),
+            "application/cloudevents-batch+protobuf" => Some(ResponseEncoding::ProtobufBatch),
+            _ => None,
+        }
+    }
+}
+
+impl From<&Encoding> for ResponseEncoding {
+    fn from(encoding: &Encoding) -> Self {
+        match encoding {
+            Encoding::BINARY => ResponseEncoding::Binary,
+            Encoding::STRUCTURED => ResponseEncoding::Structured,
+            Encoding::BATCH => ResponseEncoding::Batch,
+        }
+    }
+}
+
+impl FromStr for ResponseEncoding {
+    type Err = String;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(ResponseEncoding::Binary),
+            "structured" => Ok(ResponseEncoding::Structured),
+            "batch" => Ok(ResponseEncoding::Batch),
+            "protobuf" => Ok(ResponseEncoding::Protobuf),
+            "protobuf-batch" => Ok(ResponseEncoding::ProtobufBatch),
+            _ => Err(format!(
+                "Invalid response encoding {}, expecting binary, structured, batch, protobuf or protobuf-batch",
+                s
+            )),
+        }
+    }
+}
+
+/// Picks the cloud events media type preferred by the `Accept` header, if
+/// any. Wildcards don't select any encoding.
+pub fn negotiate(accept: &str) -> Option<ResponseEncoding> {
+    let mut best: Option<(f32, ResponseEncoding)> = None;
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let encoding = match ResponseEncoding::from_media_type(&media_type) {
+            Some(encoding) => encoding,
+            None => continue,
+        };
+        let quality = parts
+            .filter_map(|param| {
+                let mut kv = param.splitn(2, '=');
+                match (kv.next().map(str::trim), kv.next()) {
+                    (Some("q"), Some(q)) => q.trim().parse::<f32>().ok(),
+                    _ => None,
+                }
+            })
+            .next()
+            .unwrap_or(1.0);
+        // On equal quality the first media type wins
+        if quality > 0.0 && best.is_none_or(|(q, _)| quality > q) {
+            best = Some((quality, encoding));
+        }
+    }
+    best.map(|(_, encoding)| encoding)
+}
+
+/// Writes the events in `encoding`. Multiple events are always written as a
+/// batch, in JSON unless protobuf was requested.
 pub fn write_cloud_event(
     mut ce: Vec<Event>,
-    e: Option<Encoding>,
+    encoding: ResponseEncoding,
 ) -> Result<HttpResponse, actix_web::Error> {
-    if ce.len() == 1 {
-        let encoding = e.unwrap_or(DEFAULT_ENCODING);
-
-        return match encoding {
-            Encoding::STRUCTURED => write_structured(ce.remove(0)),
-            _ => write_binary(ce.remove(0)),
-        };
-    } else if ce.len() == 0 {
+    if ce.is_empty() {
         return Ok(HttpResponse::Accepted().finish());
-    } else {
-        unimplemented!()
+    }
+    match encoding {
+        ResponseEncoding::Binary if ce.len() == 1 => write_binary(ce.remove(0)),
+        ResponseEncoding::Structured if ce.len() == 1 => write_structured(ce.remove(0)),
+        ResponseEncoding::Protobuf if ce.len() == 1 => Ok(HttpResponse::Ok()
+            .content_type("application/cloudevents+protobuf")
+            .body(protobuf::encode_event(&ce[0]))),
+        ResponseEncoding::Protobuf | ResponseEncoding::ProtobufBatch => Ok(HttpResponse::Ok()
+            .content_type("application/cloudevents-batch+protobuf")
+            .body(protobuf::encode_batch(&ce))),
+        _ => write_batch(ce),
     }
 }
 
+/// Headers of the event in binary encoding, except the content type.
+pub(crate) fn binary_headers(event: &Event) -> Vec<(String, String)> {
+    let mut headers = vec![
+        (String::from(CE_ID_HEADER), event.id.clone()),
+        (
+            String::from(CE_SPECVERSION_HEADER),
+            event.spec_version.to_string(),
+        ),
+        (String::from(CE_SOURCE_HEADER), event.source.clone()),
+        (String::from(CE_TYPE_HEADER), event.event_type.clone()),
+    ];
+    if let Some(sub) = &event.subject {
+        headers.push((String::from(CE_SUBJECT_HEADER), sub.clone()));
+    }
+    if let Some(time) = &event.time {
+        headers.push((String::from(CE_TIME_HEADER), time.to_rfc3339()));
+    }
+    for (name, value) in &event.extensions {
+        // The distributed tracing extension maps to the W3C trace context headers
+        if name == TRACEPARENT || name == TRACESTATE {
+            headers.push((name.clone(), value.clone()));
+        } else {
+            headers.push((format!("ce-{}", name), value.clone()));
+        }
+    }
+    headers
+}
+
 fn write_binary(event: Event) -> Result<HttpResponse, actix_web::Error> {
-    // Write headers
     let mut builder = HttpResponse::Ok();
-    builder.header(CE_ID_HEADER, event.id);
-    builder.header(CE_SPECVERSION_HEADER, event.spec_version.to_string());
-    builder.header(CE_SOURCE_HEADER, event.source);
-    builder.header(CE_TYPE_HEADER, event.event_type);
-    if let Some(sub) = event.subject {
-        builder.header(CE_SUBJECT_HEADER, sub);
-    }
-    if let Some(time) = event.time {
-        builder.header(CE_TIME_HEADER, time.to_rfc3339());
+    for (name, value) in binary_headers(&event) {
+        builder.header(name.as_str(), value);
     }
     let result = if let Some(p) = event.payload {
         builder.content_type(p.content_type).body(p.data)
@@ -53,3 +160,46 @@ fn write_structured(event: Event) -> Result<HttpResponse, actix_web::Error> {
         })
         .map_err(|e| actix_web::error::ErrorInternalServerError(e))
 }
+
+fn write_batch(events: Vec<Event>) -> Result<HttpResponse, actix_web::Error> {
+    serde_json::to_vec(&events)
+        .map(|j| {
+            HttpResponse::Ok()
+                .content_type("application/cloudevents-batch+json")
+                .body(j)
+        })
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_accept() {
+        assert_eq!(
+            negotiate("application/cloudevents+json"),
+            Some(ResponseEncoding::Structured)
+        );
+        assert_eq!(
+            negotiate("application/cloudevents+json;q=0.5, application/cloudevents-batch+protobuf"),
+            Some(ResponseEncoding::ProtobufBatch)
+        );
+        assert_eq!(
+            negotiate("text/html, application/cloudevents-batch+json;q=0.8, */*;q=0.1"),
+            Some(ResponseEncoding::Batch)
+        );
+        assert_eq!(negotiate("application/cloudevents+protobuf;q=0"), None);
+        assert_eq!(negotiate("*/*"), None);
+    }
+
+    #[test]
+    fn test_multiple_events_are_written_as_batch() {
+        let events = vec![Event::new(), Event::new()];
+        let res = write_cloud_event(events, ResponseEncoding::Binary).unwrap();
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/cloudevents-batch+json"
+        );
+    }
+}
@@ -0,0 +1,760 @@
+use crate::activation::{self, Inherited};
+use crate::config::RuntimeConfig;
+use crate::context::InvocationTimeout;
+use crate::dead_letter::DeadLetterQueue;
+use crate::dedup::Deduplicator;
+use crate::emitter::Emitter;
+use crate::error::ErrorEvents;
+use crate::h2c;
+use crate::health::{self, Health};
+use crate::limit::ConcurrencyLimiter;
+use crate::logging::{self, LogFormat};
+use crate::metrics::{self, Metrics};
+use crate::payload::PayloadLimits;
+use crate::response_writer::ResponseEncoding;
+use crate::shutdown::{self, PendingWork};
+use crate::sink::Sink;
+use crate::tls::TlsConfig;
+use crate::trace::{LogExporter, SpanExporter, Tracer};
+use crate::transform::{transform_fn, BoxedService, TransformFn, Transforms};
+use crate::uds::{self, SocketPermissions};
+use actix_web::dev::{MessageBody, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::web::{PayloadConfig, ServiceConfig};
+use actix_web::{HttpServer, Route};
+use futures::future::{ok, Either, FutureExt, LocalBoxFuture};
+use serde::{de, Deserialize, Deserializer};
+use std::fmt;
+use std::future::Future;
+use std::net::{SocketAddr, TcpListener};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+type RouteFn = Arc<dyn Fn(Route) -> Route + Send + Sync>;
+type ConfigureFn = Arc<dyn Fn(&mut ServiceConfig) + Send + Sync>;
+type Hook = Arc<dyn Fn() -> LocalBoxFuture<'static, ()> + Send + Sync>;
+type ListenHook = Arc<dyn Fn(&[BindAddress]) + Send + Sync>;
+
+/// Hooks invoked around every request served by the runtime. The hooks are
+/// synchronous, middlewares which need to await, like authentication against
+/// a remote service, are registered with `Runtime::wrap_transform`.
+pub trait Middleware: Send + Sync + 'static {
+    /// Invoked before the request reaches the function. Returning an error
+    /// short circuits the request with the error response.
+    fn on_request(&self, _req: &ServiceRequest) -> Result<(), actix_web::Error> {
+        Ok(())
+    }
+
+    /// Invoked with the response, before it's sent back to the caller.
+    fn on_response(&self, _res: &mut ServiceResponse) {}
+}
+
+/// Where the runtime accepts connections. Parsed from socket addresses,
+/// e.g. `127.0.0.1:8080` or `[::]:8080`, and `unix:<path>` for Unix Domain
+/// Sockets.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BindAddress {
+    Tcp(SocketAddr),
+    Uds(String),
+}
+
+impl FromStr for BindAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_prefix("unix:") {
+            Some("") => Err(String::from("Missing Unix Domain Socket path")),
+            Some(path) => Ok(BindAddress::Uds(path.to_string())),
+            None => s
+                .parse::<SocketAddr>()
+                .map(BindAddress::Tcp)
+                .map_err(|e| format!("Invalid address {}: {}", s, e)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BindAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for BindAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindAddress::Tcp(addr) => write!(f, "{}", addr),
+            BindAddress::Uds(path) => write!(f, "unix:{}", path),
+        }
+    }
+}
+
+/// Builder to configure and start the FaaS runtime.
+///
+/// ```ignore
+/// Runtime::new()
+///     .bind(([127, 0, 0, 1], 8080).into())
+///     .workers(2)
+///     .function("/orders", orders::route)
+///     .run()
+///     .await
+/// ```
+#[derive(Clone)]
+pub struct Runtime {
+    listeners: Vec<BindAddress>,
+    socket_activation: bool,
+    socket_permissions: SocketPermissions,
+    tls: Option<TlsConfig>,
+    h2c: bool,
+    log: bool,
+    log_format: LogFormat,
+    log_filter: Option<String>,
+    workers: Option<usize>,
+    shutdown_timeout: Option<u64>,
+    payload_limits: PayloadLimits,
+    invocation_timeout: Option<u64>,
+    routes: Vec<(String, RouteFn)>,
+    configurators: Vec<ConfigureFn>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    transforms: Vec<TransformFn>,
+    config: RuntimeConfig,
+    liveness_path: String,
+    readiness_path: String,
+    health: Health,
+    pending: PendingWork,
+    metrics: Option<(String, Metrics)>,
+    tracer: Option<Tracer>,
+    init_hooks: Vec<Hook>,
+    shutdown_hooks: Vec<Hook>,
+    listen_hooks: Vec<ListenHook>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime {
+            listeners: Vec::new(),
+            socket_activation: true,
+            socket_permissions: SocketPermissions::default(),
+            tls: None,
+            h2c: false,
+            log: false,
+            log_format: LogFormat::Text,
+            log_filter: None,
+            workers: None,
+            shutdown_timeout: None,
+            payload_limits: PayloadLimits::default(),
+            invocation_timeout: None,
+            routes: Vec::new(),
+            configurators: Vec::new(),
+            middlewares: Vec::new(),
+            transforms: Vec::new(),
+            config: RuntimeConfig::default(),
+            liveness_path: String::from(health::DEFAULT_LIVENESS_PATH),
+            readiness_path: String::from(health::DEFAULT_READINESS_PATH),
+            health: Health::default(),
+            pending: PendingWork::default(),
+            metrics: None,
+            tracer: None,
+            init_hooks: Vec::new(),
+            shutdown_hooks: Vec::new(),
+            listen_hooks: Vec::new(),
+        }
+    }
+}
+
+impl Runtime {
+    /// Creates the runtime with the default configuration, registered as
+    /// data available to the functions.
+    pub fn new() -> Runtime {
+        Runtime::default()
+    }
+
+    /// Creates the runtime configured with `config`, which is also registered
+    /// as data available to the functions.
+    pub fn from_config(config: RuntimeConfig) -> Runtime {
+        let mut runtime = config
+            .bind_addresses()
+            .into_iter()
+            .fold(Runtime::new(), Runtime::listen)
+            .socket_activation(config.socket_activation)
+            .socket_permissions(config.socket_permissions())
+            .log(config.log)
+            .log_format(config.log_format)
+            .health_paths(config.liveness_path.clone(), config.readiness_path.clone());
+        if let Some(log_filter) = &config.log_filter {
+            runtime = runtime.log_filter(log_filter.clone());
+        }
+        if let Some(tls) = config.tls() {
+            runtime = runtime.tls(tls);
+        }
+        if config.h2c {
+            runtime = runtime.h2c(true);
+        }
+        if config.metrics {
+            runtime = runtime
+                .metrics(config.metrics_path.clone())
+                .metrics_event_types(config.metrics_event_types.clone());
+        }
+        if config.tracing {
+            runtime = runtime.tracing(LogExporter);
+        }
+        if let Some(sink) = config.sink() {
+            runtime = runtime.sink(sink);
+        }
+        if let Some(emitter) = config.emitter() {
+            runtime = runtime.emitter(emitter);
+        }
+        if let Some(dead_letters) = config.dead_letters() {
+            runtime = runtime.dead_letters(dead_letters);
+        }
+        if let Some(encoding) = config.response_encoding {
+            runtime = runtime.response_encoding(encoding);
+        }
+        if let Some(deduplicator) = config.deduplicator() {
+            runtime = runtime.deduplicator(deduplicator);
+        }
+        if config.error_events {
+            runtime = runtime.error_events(ErrorEvents {
+                event_type: config.error_event_type.clone(),
+                source: config.error_event_source.clone(),
+            });
+        }
+        if let Some(workers) = config.workers {
+            runtime = runtime.workers(workers);
+        }
+        if let Some(shutdown_timeout) = config.shutdown_timeout {
+            runtime = runtime.shutdown_timeout(shutdown_timeout);
+        }
+        runtime = runtime.payload_limits(config.payload_limits());
+        if let Some(invocation_timeout) = config.invocation_timeout {
+            runtime = runtime.invocation_timeout(invocation_timeout);
+        }
+        if config.concurrency_limit.is_some() || !config.event_type_concurrency_limits.is_empty() {
+            let mut limiter = ConcurrencyLimiter::new().queue_size(config.concurrency_queue_size);
+            if let Some(limit) = config.concurrency_limit {
+                limiter = limiter.limit(limit);
+            }
+            for (event_type, limit) in &config.event_type_concurrency_limits {
+                limiter = limiter.event_type_limit(event_type.clone(), *limit);
+            }
+            if let Some(queue_timeout) = config.concurrency_queue_timeout {
+                limiter = limiter.queue_timeout(Duration::from_secs(queue_timeout));
+            }
+            if let Some(retry_after) = config.retry_after {
+                limiter = limiter.retry_after(retry_after);
+            }
+            runtime = runtime.concurrency_limiter(limiter);
+        }
+        runtime.config = config;
+        runtime
+    }
+
+    /// Enables the logging, including the access log. The runtime is silent
+    /// by default.
+    pub fn log(mut self, enable: bool) -> Self {
+        self.log = enable;
+        self
+    }
+
+    pub fn log_format(mut self, format: LogFormat) -> Self {
+        self.log_format = format;
+        self
+    }
+
+    /// Log filter, in the `env_logger` format, e.g. `info,actix_web=warn`.
+    /// Enables the logging.
+    pub fn log_filter<S: Into<String>>(mut self, filter: S) -> Self {
+        self.log_filter = Some(filter.into());
+        self
+    }
+
+    /// Adds a listener. When no listener is configured, the runtime listens
+    /// on `0.0.0.0:8080`.
+    pub fn listen(mut self, address: BindAddress) -> Self {
+        self.listeners.push(address);
+        self
+    }
+
+    /// Adds a TCP listener. With port 0 the port is assigned by the OS and
+    /// reported to the `on_listen` hooks.
+    pub fn bind(self, addr: SocketAddr) -> Self {
+        self.listen(BindAddress::Tcp(addr))
+    }
+
+    /// Adds a Unix Domain Socket listener. A stale socket left by a previous
+    /// process is replaced, and the socket is removed on shutdown.
+    pub fn bind_uds<S: Into<String>>(self, path: S) -> Self {
+        self.listen(BindAddress::Uds(path.into()))
+    }
+
+    /// Mode and owner of the Unix Domain Sockets created by the runtime.
+    pub fn socket_permissions(mut self, permissions: SocketPermissions) -> Self {
+        self.socket_permissions = permissions;
+        self
+    }
+
+    /// Serves on the sockets passed by systemd with socket activation, when
+    /// `LISTEN_PID` and `LISTEN_FDS` are set, instead of binding the
+    /// listeners. Enabled by default.
+    pub fn socket_activation(mut self, enable: bool) -> Self {
+        self.socket_activation = enable;
+        self
+    }
+
+    /// Serves HTTPS on the TCP address. The certificate is reloaded when its
+    /// files change.
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Accepts HTTP/2 connections with prior knowledge alongside HTTP/1.1 on
+    /// the TCP address. With TLS, HTTP/2 is always negotiated through ALPN.
+    pub fn h2c(mut self, enable: bool) -> Self {
+        self.h2c = enable;
+        self
+    }
+
+    /// Number of worker threads. Defaults to the number of logical cpus.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = Some(workers);
+        self
+    }
+
+    /// Seconds the workers have to complete the in-flight requests when
+    /// the runtime receives SIGTERM or SIGINT. Defaults to 30 seconds.
+    pub fn shutdown_timeout(mut self, seconds: u64) -> Self {
+        self.shutdown_timeout = Some(seconds);
+        self
+    }
+
+    /// Maximum size in bytes of the request payload. Defaults to 256 KiB.
+    pub fn payload_limit(self, bytes: usize) -> Self {
+        self.payload_limits(PayloadLimits::all(bytes))
+    }
+
+    /// Maximum size in bytes of the request payload, by encoding. Larger
+    /// requests are rejected with `413 Payload Too Large`.
+    pub fn payload_limits(mut self, limits: PayloadLimits) -> Self {
+        self.payload_limits = limits;
+        self
+    }
+
+    /// Seconds an invocation can run before it's cancelled and replied with
+    /// `504 Gateway Timeout`, not counting the time waiting for the
+    /// concurrency limits. Requests can shorten it with a deadline header.
+    pub fn invocation_timeout(mut self, seconds: u64) -> Self {
+        self.invocation_timeout = Some(seconds);
+        self
+    }
+
+    /// Limits the invocations in progress. The limiter is shared by all the
+    /// workers.
+    pub fn concurrency_limiter(self, limiter: ConcurrencyLimiter) -> Self {
+        self.data(limiter)
+    }
+
+    /// Mounts a function generated by `faas_function` on the provided path.
+    pub fn function<S: Into<String>>(self, path: S, route_mod_fn: fn(Route) -> Route) -> Self {
+        self.route(path, route_mod_fn)
+    }
+
+    /// Mounts a handler on the provided path. The route provided to
+    /// `route_mod_fn` already accepts only `GET` and `POST` requests.
+    pub fn route<S, F>(mut self, path: S, route_mod_fn: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(Route) -> Route + Send + Sync + 'static,
+    {
+        self.routes.push((path.into(), Arc::new(route_mod_fn)));
+        self
+    }
+
+    /// Registers additional services, routes or data on the runtime app.
+    pub fn configure<F>(mut self, configure_fn: F) -> Self
+    where
+        F: Fn(&mut ServiceConfig) + Send + Sync + 'static,
+    {
+        self.configurators.push(Arc::new(configure_fn));
+        self
+    }
+
+    /// Registers data available to the handlers through `web::Data<T>`.
+    /// Every worker gets its own clone of `data`, so wrap in an `Arc`
+    /// the state that must be shared.
+    pub fn data<T: Clone + Send + Sync + 'static>(self, data: T) -> Self {
+        self.configure(move |cfg| {
+            cfg.data(data.clone());
+        })
+    }
+
+    pub fn wrap<M: Middleware>(mut self, middleware: M) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Wraps the functions in an actix middleware, e.g. `Cors` or
+    /// `Compress`. `factory` creates the middleware of every worker. The
+    /// middlewares registered later are the outer ones, and they all run
+    /// outside of the `Middleware` hooks.
+    ///
+    /// ```ignore
+    /// Runtime::new().wrap_transform(actix_web::middleware::Compress::default)
+    /// ```
+    pub fn wrap_transform<F, T, B>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> T + Send + Sync + 'static,
+        T: Transform<
+                BoxedService,
+                Request = ServiceRequest,
+                Response = ServiceResponse<B>,
+                Error = actix_web::Error,
+                InitError = (),
+            > + 'static,
+        T::Transform: 'static,
+        T::Future: 'static,
+        <T::Transform as Service>::Future: 'static,
+        B: MessageBody + 'static,
+    {
+        self.transforms.push(transform_fn(factory));
+        self
+    }
+
+    /// Paths of the liveness and readiness probes.
+    pub fn health_paths<S: Into<String>>(mut self, liveness_path: S, readiness_path: S) -> Self {
+        self.liveness_path = liveness_path.into();
+        self.readiness_path = readiness_path.into();
+        self
+    }
+
+    /// Exposes the invocation metrics in the Prometheus text format on the
+    /// provided path.
+    pub fn metrics<S: Into<String>>(mut self, path: S) -> Self {
+        let metrics = self
+            .metrics
+            .take()
+            .map(|(_, metrics)| metrics)
+            .unwrap_or_default();
+        self.metrics = Some((path.into(), metrics));
+        self
+    }
+
+    /// Event types counted with their own label in the metrics, the others
+    /// are counted as `other`. Enables the metrics on the default path if
+    /// they're not enabled yet.
+    pub fn metrics_event_types<I, S>(mut self, event_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let (path, metrics) = self
+            .metrics
+            .take()
+            .unwrap_or_else(|| (String::from(metrics::DEFAULT_METRICS_PATH), Metrics::new()));
+        self.metrics = Some((path, metrics.event_types(event_types)));
+        self
+    }
+
+    /// Creates an OpenTelemetry span for every invocation, exported through
+    /// `exporter`, e.g. the one of `opentelemetry-otlp` or `LogExporter`.
+    pub fn tracing<E: SpanExporter + 'static>(mut self, exporter: E) -> Self {
+        self.tracer = Some(Tracer::new(exporter));
+        self
+    }
+
+    /// Encoding of the responses when the caller doesn't accept a specific
+    /// one and the function doesn't override it. Defaults to the encoding of
+    /// the request.
+    pub fn response_encoding(self, encoding: ResponseEncoding) -> Self {
+        self.data(encoding)
+    }
+
+    /// Sends the events returned by the functions to `sink`, replying
+    /// `202 Accepted` to the caller.
+    pub fn sink(self, sink: Sink) -> Self {
+        self.data(sink)
+    }
+
+    /// Registers the emitter injected in the functions declaring an argument
+    /// of type `Emitter`.
+    pub fn emitter(self, emitter: Emitter) -> Self {
+        self.data(emitter)
+    }
+
+    /// Records the events which couldn't be processed or delivered.
+    pub fn dead_letters(self, queue: DeadLetterQueue) -> Self {
+        self.data(queue)
+    }
+
+    /// Invokes the functions once for every event source and id, replaying
+    /// the cached response to the duplicates.
+    pub fn deduplicator(self, deduplicator: Deduplicator) -> Self {
+        self.data(deduplicator)
+    }
+
+    /// Replies to the failed invocations with error events, in the encoding
+    /// of the response.
+    pub fn error_events(self, error_events: ErrorEvents) -> Self {
+        self.data(error_events)
+    }
+
+    /// Registers a hook invoked when the runtime is started. The runtime is
+    /// ready only after all the init hooks are completed.
+    pub fn on_init<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.init_hooks.push(Arc::new(move || hook().boxed_local()));
+        self
+    }
+
+    /// Registers the services and data of the runtime on the app of a worker.
+    fn configure_app(&self, cfg: &mut ServiceConfig) {
+        health::configure(
+            cfg,
+            &self.liveness_path,
+            &self.readiness_path,
+            self.health.clone(),
+        );
+        if let Some((metrics_path, metrics)) = &self.metrics {
+            metrics::configure(cfg, metrics_path, metrics.clone());
+        }
+        if let Some(tracer) = &self.tracer {
+            cfg.data(tracer.clone());
+        }
+        cfg.data(self.config.clone());
+        cfg.data(self.payload_limits);
+        cfg.data(self.pending.clone());
+        if let Some(seconds) = self.invocation_timeout {
+            cfg.data(InvocationTimeout(Duration::from_secs(seconds)));
+        }
+        for configure_fn in &self.configurators {
+            configure_fn(cfg);
+        }
+        for (path, route_mod_fn) in &self.routes {
+            cfg.route(path, route_mod_fn(crate::function_route()));
+        }
+    }
+
+    /// Registers a hook invoked when the runtime is shutting down, after the
+    /// in-flight invocations completed.
+    pub fn on_shutdown<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.shutdown_hooks
+            .push(Arc::new(move || hook().boxed_local()));
+        self
+    }
+
+    /// Registers a hook invoked with the addresses the runtime is actually
+    /// listening on, once they're bound.
+    pub fn on_listen<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&[BindAddress]) + Send + Sync + 'static,
+    {
+        self.listen_hooks.push(Arc::new(hook));
+        self
+    }
+
+    pub async fn run(self) -> std::io::Result<()> {
+        logging::init(self.log, self.log_format, self.log_filter.as_deref());
+
+        let listeners = if self.listeners.is_empty() {
+            vec![BindAddress::Tcp(([0, 0, 0, 0], 8080).into())]
+        } else {
+            self.listeners.clone()
+        };
+        let listen_hooks = self.listen_hooks.clone();
+        let tls = self.tls.clone();
+        let workers = self.workers;
+        let shutdown_timeout = self.shutdown_timeout;
+        let health = self.health.clone();
+        let pending = self.pending.clone();
+        let init_hooks = self.init_hooks.clone();
+        let shutdown_hooks = self.shutdown_hooks.clone();
+        let socket_activation = self.socket_activation;
+        let socket_permissions = self.socket_permissions;
+
+        let h2c = self.h2c;
+
+        let app_factory = move || {
+            let runtime = self.clone();
+            let middlewares = self.middlewares.clone();
+            let transforms = Transforms(self.transforms.clone());
+
+            // Functions check the limit of each encoding, the other handlers the largest one
+            let limit = runtime.payload_limits.max();
+            let app = actix_web::App::new()
+                .app_data(PayloadConfig::new(limit))
+                .configure(move |cfg| runtime.configure_app(cfg));
+
+            app.wrap_fn(move |req, srv| {
+                let middlewares = middlewares.clone();
+                let fut = match middlewares.iter().try_for_each(|m| m.on_request(&req)) {
+                    Ok(()) => Either::Left(srv.call(req)),
+                    Err(e) => Either::Right(ok(req.error_response(e))),
+                };
+                async move {
+                    let mut res = fut.await?;
+                    for m in middlewares.iter() {
+                        m.on_response(&mut res);
+                    }
+                    Ok(res)
+                }
+            })
+            .wrap(transforms)
+            .wrap(actix_web::middleware::Logger::default())
+        };
+
+        let inherited = if socket_activation {
+            activation::inherited_listeners()?
+        } else {
+            Vec::new()
+        };
+
+        // Listeners are bound upfront to know the port assigned by the OS
+        let mut tcp_listeners = Vec::new();
+        let mut uds_listeners = Vec::new();
+        // Sockets created by the runtime, removed when dropped
+        let mut socket_files = Vec::new();
+        let mut bound = Vec::new();
+        if !inherited.is_empty() {
+            log::info!(
+                "FaaS Runtime: Serving {} sockets passed by systemd",
+                inherited.len()
+            );
+            for listener in inherited {
+                match listener {
+                    Inherited::Tcp(tcp_listener) => {
+                        bound.push(BindAddress::Tcp(tcp_listener.local_addr()?));
+                        tcp_listeners.push(tcp_listener);
+                    }
+                    Inherited::Uds(uds_listener) => {
+                        let path = uds_listener
+                            .local_addr()?
+                            .as_pathname()
+                            .map(|p| p.display().to_string())
+                            .unwrap_or_else(|| String::from("(unnamed)"));
+                        bound.push(BindAddress::Uds(path));
+                        uds_listeners.push(uds_listener);
+                    }
+                }
+            }
+        } else {
+            for listener in &listeners {
+                match listener {
+                    BindAddress::Tcp(addr) => {
+                        let tcp_listener = TcpListener::bind(addr)?;
+                        bound.push(BindAddress::Tcp(tcp_listener.local_addr()?));
+                        tcp_listeners.push(tcp_listener);
+                    }
+                    BindAddress::Uds(path) => {
+                        let (uds_listener, socket_file) = uds::bind(path, &socket_permissions)?;
+                        uds_listeners.push(uds_listener);
+                        socket_files.push(socket_file);
+                        bound.push(listener.clone());
+                    }
+                }
+            }
+        }
+
+        let h2c = h2c && tls.is_none();
+        let server = if h2c {
+            h2c::server(
+                tcp_listeners,
+                uds_listeners,
+                workers,
+                shutdown_timeout,
+                app_factory,
+            )?
+        } else {
+            let mut server = HttpServer::new(app_factory);
+            if let Some(workers) = workers {
+                server = server.workers(workers);
+            }
+            if let Some(shutdown_timeout) = shutdown_timeout {
+                server = server.shutdown_timeout(shutdown_timeout);
+            }
+            server = server.disable_signals();
+            for tcp_listener in tcp_listeners {
+                server = match &tls {
+                    Some(tls) => server.listen_rustls(tcp_listener, tls.server_config()?)?,
+                    None => server.listen(tcp_listener)?,
+                };
+            }
+            for uds_listener in uds_listeners {
+                server = server.listen_uds(uds_listener)?;
+            }
+            server.run()
+        };
+
+        for address in &bound {
+            let protocol = match address {
+                BindAddress::Tcp(_) if tls.is_some() => " (HTTPS)",
+                BindAddress::Tcp(_) if h2c => " (HTTP/1.1 and h2c)",
+                _ => "",
+            };
+            log::info!(
+                "FaaS Runtime: Starting server listening {}{}",
+                address,
+                protocol
+            );
+        }
+        for hook in &listen_hooks {
+            hook(&bound);
+        }
+
+        shutdown::stop_on_signal(server.clone(), health.clone(), pending, shutdown_timeout);
+        actix_rt::spawn(async move {
+            run_hooks(&init_hooks).await;
+            health.set_ready(true);
+        });
+        let result = server.await;
+
+        run_hooks(&shutdown_hooks).await;
+        drop(socket_files);
+
+        result
+    }
+}
+
+async fn run_hooks(hooks: &[Hook]) {
+    for hook in hooks {
+        hook().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+
+    fn port(config: web::Data<RuntimeConfig>) -> HttpResponse {
+        HttpResponse::Ok().body(config.port.to_string())
+    }
+
+    #[actix_rt::test]
+    async fn test_runtime_config_is_registered() {
+        let configured = Runtime::from_config(RuntimeConfig {
+            port: 9090,
+            ..RuntimeConfig::default()
+        });
+        for (runtime, expected) in [(Runtime::new(), "8080"), (configured, "9090")] {
+            let runtime = runtime.route("/", |route| route.to(port));
+            let mut app =
+                test::init_service(App::new().configure(|cfg| runtime.configure_app(cfg))).await;
+
+            let req = TestRequest::post().uri("/").to_request();
+            let res = test::call_service(&mut app, req).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(test::read_body(res).await, expected);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::health::Health;
+use actix_web::dev::Server;
+use futures::future::select;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default of the actix server.
+const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
+const DRAIN_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Work the runtime completes before stopping: the invocations in progress
+/// and the tasks they spawned in the background, like the events emitted at
+/// most once.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PendingWork(Arc<AtomicUsize>);
+
+impl PendingWork {
+    /// Tracks the work until the guard is dropped.
+    pub(crate) fn start(&self) -> PendingGuard {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        PendingGuard(self.0.clone())
+    }
+
+    /// Spawns `task` on the current worker, tracking it.
+    pub(crate) fn spawn<F: Future<Output = ()> + 'static>(&self, task: F) {
+        let guard = self.start();
+        actix_rt::spawn(async move {
+            task.await;
+            drop(guard);
+        });
+    }
+
+    /// Resolves when there is no pending work, or after `timeout`.
+    async fn drain(&self, timeout: Duration) {
+        let start = Instant::now();
+        while self.0.load(Ordering::SeqCst) > 0 {
+            if start.elapsed() >= timeout {
+                log::warn!(
+                    "FaaS Runtime: Stopping with {} invocations or background tasks pending",
+                    self.0.load(Ordering::SeqCst)
+                );
+                return;
+            }
+            actix_rt::time::delay_for(DRAIN_INTERVAL).await;
+        }
+    }
+}
+
+pub(crate) struct PendingGuard(Arc<AtomicUsize>);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Resolves when the process receives SIGTERM or SIGINT.
+async fn wait_for_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use actix_rt::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        select(Box::pin(terminate.recv()), Box::pin(interrupt.recv())).await;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        actix_rt::signal::ctrl_c().await
+    }
+}
+
+/// Stops the server when a termination signal is received. The runtime is
+/// marked as not ready, then the server stops accepting new connections and
+/// waits for the in-flight invocations and their background tasks to
+/// complete, up to the configured shutdown timeout. The background tasks run
+/// on the workers, so they're drained before the workers are stopped.
+pub(crate) fn stop_on_signal(
+    server: Server,
+    health: Health,
+    pending: PendingWork,
+    shutdown_timeout: Option<u64>,
+) {
+    actix_rt::spawn(async move {
+        if let Err(e) = wait_for_signal().await {
+            log::error!("FaaS Runtime: Cannot listen for termination signals: {}", e);
+            return;
+        }
+        log::info!("FaaS Runtime: Shutting down, waiting for in-flight invocations");
+        health.set_ready(false);
+        server.pause().await;
+        let timeout = shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT);
+        pending.drain(Duration::from_secs(timeout)).await;
+        server.stop(true).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn test_drain_waits_for_background_tasks() {
+        let pending = PendingWork::default();
+        let invocation = pending.start();
+        pending.spawn(actix_rt::time::delay_for(Duration::from_millis(100)));
+        drop(invocation);
+
+        let start = Instant::now();
+        pending.drain(Duration::from_secs(5)).await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(pending.0.load(Ordering::SeqCst), 0);
+    }
+}
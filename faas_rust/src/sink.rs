@@ -0,0 +1,271 @@
+use crate::response_writer::binary_headers;
+use actix_web::http::StatusCode;
+use cloudevent::Event;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Env variable injected by the Knative sources and sink bindings.
+pub const K_SINK_ENV: &str = "K_SINK";
+
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkEncoding {
+    Binary,
+    Structured,
+}
+
+impl FromStr for SinkEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(SinkEncoding::Binary),
+            "structured" => Ok(SinkEncoding::Structured),
+            _ => Err(format!(
+                "Invalid sink encoding {}, expecting binary or structured",
+                s
+            )),
+        }
+    }
+}
+
+/// Destination of the events returned by the functions. When a sink is
+/// configured, the events are sent to the sink retrying with exponential
+/// backoff, and the caller gets `202 Accepted` once the sink accepted them or
+/// `502 Bad Gateway` if it didn't.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sink {
+    url: String,
+    encoding: SinkEncoding,
+    retries: u32,
+    backoff: Duration,
+    timeout: Duration,
+}
+
+impl Sink {
+    pub fn new<S: Into<String>>(url: S) -> Sink {
+        Sink {
+            url: url.into(),
+            encoding: SinkEncoding::Binary,
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Defaults to binary.
+    pub fn encoding(mut self, encoding: SinkEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Attempts after the first failed one. Defaults to 3.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Delay before the first retry, doubled at every retry up to 30
+    /// seconds. Defaults to 100 milliseconds.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Timeout of every attempt. Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends the event, retrying the connection failures, the timeouts and
+    /// the `408`, `429` and `5xx` responses.
+    pub async fn send(&self, event: &Event) -> Result<(), DeliveryError> {
+        let mut delay = self.backoff;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let (reason, retryable) = match self.post(event).await {
+                Ok(status) if status.is_success() => return Ok(()),
+                Ok(status) => (format!("the sink replied {}", status), is_retryable(status)),
+                Err(e) => (e, true),
+            };
+            if !retryable || attempts > self.retries {
+                return Err(DeliveryError { attempts, reason });
+            }
+            log::debug!(
+                "FaaS Runtime: Retrying delivery of event {} to {}: {}",
+                event.id,
+                self.url,
+                reason
+            );
+            actix_rt::time::delay_for(delay).await;
+            delay = (delay * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    async fn post(&self, event: &Event) -> Result<StatusCode, String> {
+        let client = awc::Client::build().timeout(self.timeout).finish();
+        let mut request = client.post(self.url.as_str());
+        let body = match self.encoding {
+            SinkEncoding::Binary => {
+                for (name, value) in binary_headers(event) {
+                    request = request.header(name.as_str(), value);
+                }
+                match &event.payload {
+                    Some(payload) => {
+                        request = request.content_type(payload.content_type.as_str());
+                        payload.data.clone()
+                    }
+                    None => Vec::new(),
+                }
+            }
+            SinkEncoding::Structured => {
+                request = request.content_type("application/cloudevents+json");
+                serde_json::to_vec(event).map_err(|e| e.to_string())?
+            }
+        };
+        request
+            .send_body(body)
+            .await
+            .map(|res| res.status())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Sends the events one at a time, stopping at the first one which can't
+    /// be delivered.
+    pub(crate) async fn deliver(&self, events: &[Event]) -> Result<(), DeliveryError> {
+        for event in events {
+            if let Err(e) = self.send(event).await {
+                log::error!(
+                    "FaaS Runtime: Cannot deliver event {} to {}: {}",
+                    event.id,
+                    self.url,
+                    e
+                );
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// The event couldn't be delivered to the sink.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeliveryError {
+    pub attempts: u32,
+    pub reason: String,
+}
+
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (after {} attempts)", self.reason, self.attempts)
+    }
+}
+
+impl std::error::Error for DeliveryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invocation;
+    use actix_web::{test, web, App, HttpRequest, HttpResponse};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Starts a sink replying with `statuses`, in order, and then `202`.
+    fn mock_sink(statuses: Vec<StatusCode>) -> (test::TestServer, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let srv = test::start(move || {
+            let counter = counter.clone();
+            let statuses = statuses.clone();
+            App::new().route(
+                "/",
+                web::post().to(move |req: HttpRequest| {
+                    let n = counter.fetch_add(1, Ordering::SeqCst);
+                    let status = match req.headers().get("ce-type") {
+                        Some(t) if t == "dev.knative.example" => {
+                            statuses.get(n).cloned().unwrap_or(StatusCode::ACCEPTED)
+                        }
+                        _ => StatusCode::BAD_REQUEST,
+                    };
+                    HttpResponse::build(status).finish()
+                }),
+            )
+        });
+        (srv, requests)
+    }
+
+    fn event() -> Event {
+        let mut event = Event::new();
+        event.event_type = String::from("dev.knative.example");
+        event
+    }
+
+    #[actix_rt::test]
+    async fn test_retries_until_delivered() {
+        let (srv, requests) = mock_sink(vec![
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::TOO_MANY_REQUESTS,
+        ]);
+        let sink = Sink::new(srv.url("/")).backoff(Duration::from_millis(1));
+
+        assert_eq!(sink.send(&event()).await, Ok(()));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_rt::test]
+    async fn test_client_errors_are_not_retried() {
+        let (srv, requests) = mock_sink(vec![StatusCode::BAD_REQUEST]);
+        let sink = Sink::new(srv.url("/")).backoff(Duration::from_millis(1));
+
+        let e = sink.send(&event()).await.unwrap_err();
+        assert_eq!(e.attempts, 1);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_reply_once_delivered() {
+        let (srv, requests) = mock_sink(vec![StatusCode::SERVICE_UNAVAILABLE]);
+        let sink = Sink::new(srv.url("/")).retries(0);
+        let mut app = test::init_service(App::new().data(sink).route(
+            "/",
+            web::post().to(|req: HttpRequest, payload: web::Payload| {
+                invocation::handle(req, payload, |_, events| async move { Ok(events) })
+            }),
+        ))
+        .await;
+        let req = || {
+            test::TestRequest::post()
+                .header("ce-id", "1")
+                .header("ce-source", "/shop")
+                .header("ce-type", "dev.knative.example")
+                .header("ce-specversion", "1.0")
+                .to_request()
+        };
+
+        let res = test::call_service(&mut app, req()).await;
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+        let res = test::call_service(&mut app, req()).await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
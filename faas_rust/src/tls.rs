@@ -0,0 +1,234 @@
+use rustls::internal::pemfile;
+use rustls::sign::{self, CertifiedKey};
+use rustls::{
+    AllowAnyAuthenticatedClient, Certificate, NoClientAuth, PrivateKey, ResolvesServerCert,
+    RootCertStore, ServerConfig, SignatureScheme,
+};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Certificate and key, in PEM format, of the HTTPS server. When the client
+/// CA is provided, the clients must authenticate with a certificate signed
+/// by it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    pub client_ca_path: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn new<P: Into<PathBuf>>(cert_path: P, key_path: P) -> TlsConfig {
+        TlsConfig {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            client_ca_path: None,
+        }
+    }
+
+    pub fn client_ca<P: Into<PathBuf>>(mut self, client_ca_path: P) -> TlsConfig {
+        self.client_ca_path = Some(client_ca_path.into());
+        self
+    }
+
+    pub(crate) fn server_config(&self) -> io::Result<ServerConfig> {
+        let mut config = match &self.client_ca_path {
+            Some(client_ca_path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(client_ca_path)? {
+                    roots.add(&cert).map_err(|e| {
+                        invalid_data(client_ca_path, &format!("invalid certificate: {}", e))
+                    })?;
+                }
+                ServerConfig::new(AllowAnyAuthenticatedClient::new(roots))
+            }
+            None => ServerConfig::new(NoClientAuth::new()),
+        };
+        config.cert_resolver = Arc::new(CertificateResolver::new(
+            self.cert_path.clone(),
+            self.key_path.clone(),
+        )?);
+        Ok(config)
+    }
+}
+
+/// Serves the certificate, reloading it when the certificate or the key
+/// files change. If the new files are not valid, the previous certificate
+/// is kept.
+pub struct CertificateResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    loaded: RwLock<(CertifiedKey, Option<SystemTime>)>,
+}
+
+impl CertificateResolver {
+    pub fn new(cert_path: PathBuf, key_path: PathBuf) -> io::Result<CertificateResolver> {
+        let modified = last_modified(&cert_path, &key_path);
+        let key = load_certified_key(&cert_path, &key_path)?;
+        Ok(CertificateResolver {
+            cert_path,
+            key_path,
+            loaded: RwLock::new((key, modified)),
+        })
+    }
+
+    /// Loads the certificate and the key from the files.
+    pub fn reload(&self) -> io::Result<()> {
+        let modified = last_modified(&self.cert_path, &self.key_path);
+        let key = load_certified_key(&self.cert_path, &self.key_path)?;
+        *self.loaded.write().unwrap() = (key, modified);
+        Ok(())
+    }
+
+    fn reload_if_changed(&self) {
+        let modified = last_modified(&self.cert_path, &self.key_path);
+        if modified == self.loaded.read().unwrap().1 {
+            return;
+        }
+        match self.reload() {
+            Ok(()) => log::info!(
+                "FaaS Runtime: Reloaded TLS certificate {}",
+                self.cert_path.display()
+            ),
+            Err(e) => {
+                log::warn!("FaaS Runtime: Cannot reload TLS certificate: {}", e);
+                // Don't retry until the files change again
+                self.loaded.write().unwrap().1 = modified;
+            }
+        }
+    }
+
+    pub fn certified_key(&self) -> CertifiedKey {
+        self.loaded.read().unwrap().0.clone()
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(
+        &self,
+        _server_name: Option<webpki::DNSNameRef>,
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<CertifiedKey> {
+        self.reload_if_changed();
+        Some(self.certified_key())
+    }
+}
+
+fn last_modified(cert_path: &Path, key_path: &Path) -> Option<SystemTime> {
+    let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+    modified(cert_path).max(modified(key_path))
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> io::Result<CertifiedKey> {
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+    let signing_key = sign::any_supported_type(&key)
+        .map_err(|_| invalid_data(key_path, "unsupported private key type"))?;
+    Ok(CertifiedKey::new(certs, Arc::new(signing_key)))
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    match pemfile::certs(&mut reader) {
+        Ok(certs) if !certs.is_empty() => Ok(certs),
+        _ => Err(invalid_data(path, "no PEM certificate found")),
+    }
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut keys = pemfile::pkcs8_private_keys(&mut reader).unwrap_or_default();
+    if keys.is_empty() {
+        let mut reader = BufReader::new(File::open(path)?);
+        keys = pemfile::rsa_private_keys(&mut reader).unwrap_or_default();
+    }
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| invalid_data(path, "no PEM private key found"))
+}
+
+fn invalid_data(path: &Path, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    struct TestCerts {
+        dir: PathBuf,
+    }
+
+    impl TestCerts {
+        fn new() -> TestCerts {
+            let dir = std::env::temp_dir().join(format!("faas-tls-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            TestCerts { dir }
+        }
+
+        /// Generates a self-signed certificate, returning its DER encoding.
+        fn generate(&self) -> Vec<u8> {
+            let cert = rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+            fs::write(self.cert_path(), cert.serialize_pem().unwrap()).unwrap();
+            fs::write(self.key_path(), cert.serialize_private_key_pem()).unwrap();
+            load_certs(&self.cert_path()).unwrap().remove(0).0
+        }
+
+        fn cert_path(&self) -> PathBuf {
+            self.dir.join("tls.crt")
+        }
+
+        fn key_path(&self) -> PathBuf {
+            self.dir.join("tls.key")
+        }
+    }
+
+    impl Drop for TestCerts {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn test_reload_rotated_certificate() {
+        let certs = TestCerts::new();
+        let first = certs.generate();
+        let resolver = CertificateResolver::new(certs.cert_path(), certs.key_path()).unwrap();
+        assert_eq!(resolver.certified_key().cert[0].0, first);
+
+        let second = certs.generate();
+        resolver.reload().unwrap();
+        assert_eq!(resolver.certified_key().cert[0].0, second);
+    }
+
+    #[test]
+    fn test_invalid_rotated_key_keeps_previous_certificate() {
+        let certs = TestCerts::new();
+        let first = certs.generate();
+        let resolver = CertificateResolver::new(certs.cert_path(), certs.key_path()).unwrap();
+
+        fs::write(certs.key_path(), "not a key").unwrap();
+        assert!(resolver.reload().is_err());
+        assert_eq!(resolver.certified_key().cert[0].0, first);
+    }
+
+    #[test]
+    fn test_server_config_with_client_ca() {
+        let certs = TestCerts::new();
+        certs.generate();
+
+        let config =
+            TlsConfig::new(certs.cert_path(), certs.key_path()).client_ca(certs.cert_path());
+        assert!(config.server_config().is_ok());
+
+        let config = TlsConfig::new(certs.cert_path(), certs.dir.join("missing.key"));
+        assert!(config.server_config().is_err());
+    }
+}
@@ -0,0 +1,289 @@
+//! Tracing of the invocations with OpenTelemetry. Every invocation gets a
+//! server span continuing the W3C trace context of the request, propagated
+//! with the `traceparent` and `tracestate` headers or the cloud events
+//! distributed tracing extension.
+use actix_web::http::HeaderMap;
+use async_trait::async_trait;
+use cloudevent::Event;
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::sdk::export::trace::ExportResult;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::trace::{Span, TracerProvider};
+use opentelemetry::trace::{
+    SpanContext, SpanId, SpanKind, TraceContextExt, Tracer as _, TracerProvider as _,
+};
+use opentelemetry::Context;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use opentelemetry::sdk::export::trace::{SpanData, SpanExporter};
+
+pub const TRACEPARENT: &str = "traceparent";
+pub const TRACESTATE: &str = "tracestate";
+
+/// Creates the spans of the function invocations, sent to the exporter once
+/// completed. Only sampled spans are exported.
+#[derive(Clone)]
+pub struct Tracer {
+    // The tracer only keeps a weak reference to its provider
+    _provider: TracerProvider,
+    tracer: opentelemetry::sdk::trace::Tracer,
+}
+
+impl Tracer {
+    pub fn new<E: SpanExporter + 'static>(exporter: E) -> Tracer {
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+        let tracer = provider.versioned_tracer("faas_rust", Some(env!("CARGO_PKG_VERSION")), None);
+        Tracer {
+            _provider: provider,
+            tracer,
+        }
+    }
+
+    /// Starts a server span, child of the span of `parent` if any.
+    pub fn start_span<S: Into<String>>(&self, name: S, parent: &Context) -> Span {
+        self.tracer
+            .span_builder(name.into())
+            .with_kind(SpanKind::Server)
+            .start_with_context(&self.tracer, parent)
+    }
+}
+
+/// Trace context of the request, from the HTTP headers or, if missing, from
+/// the distributed tracing extension of `event`.
+pub(crate) fn extract(headers: &HeaderMap, event: Option<&Event>) -> Context {
+    let propagator = TraceContextPropagator::new();
+    let cx = propagator.extract(&HeaderExtractor(headers));
+    if cx.span().span_context().is_valid() {
+        return cx;
+    }
+    event
+        .map(|e| propagator.extract(&e.extensions))
+        .unwrap_or_default()
+}
+
+/// Sets the distributed tracing extension of `events` to `span_context`.
+pub(crate) fn inject(events: &mut [Event], span_context: &SpanContext) {
+    let cx = Context::new().with_remote_span_context(span_context.clone());
+    let propagator = TraceContextPropagator::new();
+    for event in events {
+        propagator.inject_context(&cx, &mut event.extensions);
+        if event
+            .extensions
+            .get(TRACESTATE)
+            .is_some_and(|s| s.is_empty())
+        {
+            event.extensions.remove(TRACESTATE);
+        }
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// Logs every span as JSON with the `faas_rust::trace` target, at info level.
+#[derive(Debug)]
+pub struct LogExporter;
+
+#[async_trait]
+impl SpanExporter for LogExporter {
+    async fn export(&mut self, batch: Vec<SpanData>) -> ExportResult {
+        for span in batch {
+            log::info!(target: "faas_rust::trace", "{}", to_json(&span));
+        }
+        Ok(())
+    }
+}
+
+fn to_json(span: &SpanData) -> serde_json::Value {
+    let attributes: HashMap<&str, String> = span
+        .attributes
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str().into_owned()))
+        .collect();
+    let parent_span_id = Some(span.parent_span_id)
+        .filter(|id| *id != SpanId::INVALID)
+        .map(|id| id.to_string());
+    json!({
+        "name": span.name,
+        "traceId": span.span_context.trace_id().to_string(),
+        "spanId": span.span_context.span_id().to_string(),
+        "parentSpanId": parent_span_id,
+        "startTimeUnixNano": unix_nanos(span.start_time),
+        "endTimeUnixNano": unix_nanos(span.end_time),
+        "status": format!("{:?}", span.status_code),
+        "attributes": attributes,
+    })
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+}
+
+/// Keeps the spans in memory, useful for tests.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryExporter {
+    spans: Arc<Mutex<Vec<SpanData>>>,
+}
+
+impl InMemoryExporter {
+    pub fn spans(&self) -> Vec<SpanData> {
+        self.spans.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl SpanExporter for InMemoryExporter {
+    async fn export(&mut self, batch: Vec<SpanData>) -> ExportResult {
+        self.spans.lock().unwrap().extend(batch);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{HeaderName, HeaderValue};
+    use opentelemetry::trace::{Span as _, TraceId};
+
+    const PARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    fn headers(traceparent: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static(TRACEPARENT),
+            HeaderValue::from_str(traceparent).unwrap(),
+        );
+        headers.insert(
+            HeaderName::from_static(TRACESTATE),
+            HeaderValue::from_static("congo=t61rcWkgMzE"),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_extract_from_headers_first() {
+        let mut event = Event::new();
+        event.extensions.insert(
+            String::from(TRACEPARENT),
+            String::from("00-11111111111111111111111111111111-2222222222222222-01"),
+        );
+
+        let cx = extract(&headers(PARENT), Some(&event));
+        let span_context = cx.span().span_context().clone();
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap()
+        );
+        assert_eq!(
+            span_context.span_id(),
+            SpanId::from_hex("b7ad6b7169203331").unwrap()
+        );
+        assert!(span_context.is_sampled());
+        assert_eq!(span_context.trace_state().header(), "congo=t61rcWkgMzE");
+
+        // Falls back to the extension
+        let cx = extract(&HeaderMap::new(), Some(&event));
+        assert_eq!(
+            cx.span().span_context().trace_id(),
+            TraceId::from_hex("11111111111111111111111111111111").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_extract_invalid_traceparent() {
+        for traceparent in &[
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        ] {
+            let cx = extract(&headers(traceparent), None);
+            assert!(!cx.span().span_context().is_valid(), "{}", traceparent);
+        }
+    }
+
+    #[test]
+    fn test_inject_into_events() {
+        let parent = extract(&headers(PARENT), None);
+        let mut events = vec![Event::new()];
+        inject(&mut events, parent.span().span_context());
+        assert_eq!(events[0].extensions[TRACEPARENT], PARENT);
+        assert_eq!(events[0].extensions[TRACESTATE], "congo=t61rcWkgMzE");
+
+        let parent = extract(&HeaderMap::new(), Some(&events[0]));
+        let span_context = SpanContext::new(
+            parent.span().span_context().trace_id(),
+            parent.span().span_context().span_id(),
+            parent.span().span_context().trace_flags(),
+            false,
+            Default::default(),
+        );
+        inject(&mut events, &span_context);
+        assert!(!events[0].extensions.contains_key(TRACESTATE));
+    }
+
+    #[test]
+    fn test_child_span_continues_trace() {
+        let exporter = InMemoryExporter::default();
+        let tracer = Tracer::new(exporter.clone());
+        let parent = extract(&headers(PARENT), None);
+
+        tracer.start_span("invoke", &parent).end();
+        // Dropping the provider waits for the pending exports
+        drop(tracer);
+
+        let spans = exporter.spans();
+        assert_eq!(spans.len(), 1);
+        let parent = parent.span().span_context().clone();
+        assert_eq!(spans[0].span_context.trace_id(), parent.trace_id());
+        assert_ne!(spans[0].span_context.span_id(), parent.span_id());
+        assert_eq!(spans[0].parent_span_id, parent.span_id());
+        assert_eq!(spans[0].span_kind, SpanKind::Server);
+    }
+
+    #[test]
+    fn test_not_sampled_span_is_not_exported() {
+        let exporter = InMemoryExporter::default();
+        let tracer = Tracer::new(exporter.clone());
+        let parent = extract(
+            &headers("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00"),
+            None,
+        );
+
+        tracer.start_span("invoke", &parent).end();
+        drop(tracer);
+
+        assert!(exporter.spans().is_empty());
+    }
+
+    #[test]
+    fn test_span_json() {
+        let exporter = InMemoryExporter::default();
+        let tracer = Tracer::new(exporter.clone());
+        let mut span = tracer.start_span("invoke", &extract(&headers(PARENT), None));
+        span.set_attribute(opentelemetry::KeyValue::new("cloudevents.event_id", "1"));
+        span.end();
+        drop(tracer);
+
+        let json = to_json(&exporter.spans()[0]);
+        assert_eq!(json["name"], "invoke");
+        assert_eq!(json["traceId"], "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(json["parentSpanId"], "b7ad6b7169203331");
+        assert_eq!(json["attributes"]["cloudevents.event_id"], "1");
+    }
+}
@@ -0,0 +1,114 @@
+//! Actix middlewares registered on the runtime. The type of the app changes
+//! with every `App::wrap`, so the middlewares are boxed and applied as a
+//! single transform of the app.
+use actix_service::boxed::{self, BoxService};
+use actix_web::dev::{
+    Body, MessageBody, ResponseBody, Service, ServiceRequest, ServiceResponse, Transform,
+};
+use futures::future::{FutureExt, LocalBoxFuture};
+use std::sync::Arc;
+
+pub(crate) type BoxedService = BoxService<ServiceRequest, ServiceResponse, actix_web::Error>;
+
+/// Builds a middleware of a worker around the service it wraps.
+pub(crate) type TransformFn =
+    Arc<dyn Fn(BoxedService) -> LocalBoxFuture<'static, Result<BoxedService, ()>> + Send + Sync>;
+
+/// Boxes the middlewares built by `factory`, mapping their responses to the
+/// body of the app.
+pub(crate) fn transform_fn<F, T, B>(factory: F) -> TransformFn
+where
+    F: Fn() -> T + Send + Sync + 'static,
+    T: Transform<
+            BoxedService,
+            Request = ServiceRequest,
+            Response = ServiceResponse<B>,
+            Error = actix_web::Error,
+            InitError = (),
+        > + 'static,
+    T::Transform: 'static,
+    T::Future: 'static,
+    <T::Transform as Service>::Future: 'static,
+    B: MessageBody + 'static,
+{
+    Arc::new(move |service: BoxedService| {
+        let transform = factory();
+        let service = transform.new_transform(service);
+        async move {
+            let service = service.await?;
+            let service = service.map(|res: ServiceResponse<B>| {
+                res.map_body(|_, body| ResponseBody::Body(Body::from_message(body)))
+            });
+            Ok::<_, ()>(boxed::service(service))
+        }
+        .boxed_local()
+    })
+}
+
+/// Wraps the app in the middlewares, the last one being the outermost as
+/// with `App::wrap`.
+pub(crate) struct Transforms(pub Vec<TransformFn>);
+
+impl<S> Transform<S> for Transforms
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>
+        + 'static,
+    S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = BoxedService;
+    type Future = LocalBoxFuture<'static, Result<BoxedService, ()>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        apply(self.0.clone(), boxed::service(service)).boxed_local()
+    }
+}
+
+async fn apply(
+    transforms: Vec<TransformFn>,
+    mut service: BoxedService,
+) -> Result<BoxedService, ()> {
+    for transform in &transforms {
+        service = transform(service).await?;
+    }
+    Ok(service)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::middleware::{Compress, DefaultHeaders};
+    use actix_web::test::{self, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+
+    #[actix_rt::test]
+    async fn test_transforms_wrap_the_app() {
+        let transforms = vec![
+            transform_fn(|| DefaultHeaders::new().header("x-layer", "inner")),
+            transform_fn(|| DefaultHeaders::new().header("x-layer", "outer")),
+            transform_fn(Compress::default),
+        ];
+        let mut app = test::init_service(
+            App::new()
+                .route(
+                    "/",
+                    web::get().to(|| HttpResponse::Ok().body("x".repeat(1024))),
+                )
+                .wrap(Transforms(transforms)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        // DefaultHeaders keeps the header set by the inner middleware
+        assert_eq!(res.headers().get("x-layer").unwrap(), "inner");
+        assert_eq!(res.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+    }
+}
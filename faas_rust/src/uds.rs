@@ -0,0 +1,187 @@
+use serde::{de, Deserialize, Deserializer};
+use std::fmt;
+use std::fs::{self, Permissions};
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Permissions of the Unix Domain Sockets created by the runtime. The owner
+/// and the group are numeric ids; changing them requires the privileges to
+/// `chown` the socket file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SocketPermissions {
+    pub mode: Option<SocketMode>,
+    pub owner: Option<u32>,
+    pub group: Option<u32>,
+}
+
+/// File mode of a socket, parsed from octal digits, e.g. `660`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SocketMode(pub u32);
+
+impl FromStr for SocketMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.trim();
+        let digits = digits.strip_prefix("0o").unwrap_or(digits);
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(SocketMode(mode)),
+            _ => Err(format!("Invalid file mode {}, expected octal digits", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SocketMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for SocketMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:o}", self.0)
+    }
+}
+
+/// Socket file created by the runtime, removed when dropped.
+pub(crate) struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.0) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => log::warn!(
+                "FaaS Runtime: Cannot remove socket {}: {}",
+                self.0.display(),
+                e
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Binds the socket at `path`, replacing the stale socket left by a previous
+/// process, and applies the `permissions`.
+pub(crate) fn bind(
+    path: &str,
+    permissions: &SocketPermissions,
+) -> io::Result<(UnixListener, SocketFile)> {
+    let path = Path::new(path);
+    remove_stale(path)?;
+    let listener = UnixListener::bind(path).map_err(|e| bind_error(path, e))?;
+    let file = SocketFile(path.to_owned());
+
+    if let Some(mode) = permissions.mode {
+        fs::set_permissions(path, Permissions::from_mode(mode.0))
+            .map_err(|e| bind_error(path, e))?;
+    }
+    if permissions.owner.is_some() || permissions.group.is_some() {
+        std::os::unix::fs::chown(path, permissions.owner, permissions.group)
+            .map_err(|e| bind_error(path, e))?;
+    }
+    Ok((listener, file))
+}
+
+/// Removes the socket at `path` if no process accepts connections on it.
+/// Other kinds of files are never removed.
+fn remove_stale(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(bind_error(path, e)),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(bind_error(
+            path,
+            io::Error::new(io::ErrorKind::AlreadyExists, "the file is not a socket"),
+        ));
+    }
+    match UnixStream::connect(path) {
+        Ok(_) => Err(bind_error(
+            path,
+            io::Error::new(io::ErrorKind::AddrInUse, "in use by another process"),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            log::warn!("FaaS Runtime: Removing stale socket {}", path.display());
+            fs::remove_file(path).map_err(|e| bind_error(path, e))
+        }
+        Err(e) => Err(bind_error(path, e)),
+    }
+}
+
+fn bind_error(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(
+        e.kind(),
+        format!("Cannot bind socket {}: {}", path.display(), e),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new() -> TestDir {
+            let dir = std::env::temp_dir().join(format!("faas-uds-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+
+        fn socket_path(&self) -> String {
+            self.0.join("function.sock").display().to_string()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_replaces_stale_socket() {
+        let dir = TestDir::new();
+        let path = dir.socket_path();
+        // The file is left behind, like after a crash
+        drop(UnixListener::bind(&path).unwrap());
+
+        let permissions = SocketPermissions {
+            mode: Some("660".parse().unwrap()),
+            ..SocketPermissions::default()
+        };
+        let (_listener, file) = bind(&path, &permissions).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o660);
+
+        drop(file);
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_rejects_socket_in_use_and_other_files() {
+        let dir = TestDir::new();
+        let path = dir.socket_path();
+        let (_listener, _file) = bind(&path, &SocketPermissions::default()).unwrap();
+        let e = bind(&path, &SocketPermissions::default()).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::AddrInUse);
+
+        let other = dir.0.join("other").display().to_string();
+        fs::write(&other, "data").unwrap();
+        assert!(bind(&other, &SocketPermissions::default()).is_err());
+        assert!(Path::new(&other).exists());
+    }
+
+    #[test]
+    fn test_parse_socket_mode() {
+        assert_eq!("660".parse(), Ok(SocketMode(0o660)));
+        assert_eq!("0o600".parse(), Ok(SocketMode(0o600)));
+        assert!("rw".parse::<SocketMode>().is_err());
+        assert!("99".parse::<SocketMode>().is_err());
+    }
+}
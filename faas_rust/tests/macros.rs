@@ -0,0 +1,101 @@
+//! Functions generated by `faas_function`, mounted on their own paths by
+//! `faas_main!` or in an app through the generated `configure`. The `main`
+//! of `faas_main!` is stopped with SIGTERM, so it's the only server of the
+//! process.
+#![cfg(unix)]
+
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use actix_web::App;
+use cloudevent::Event;
+use faas_rust_macro::faas_function;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+fn reply(mut event: Event, event_type: &str) -> Event {
+    event.event_type = String::from(event_type);
+    event
+}
+
+#[faas_function(path = "/orders")]
+pub async fn orders(event: Event) -> Result<Event, actix_web::Error> {
+    Ok(reply(event, "order.accepted"))
+}
+
+#[faas_function(path = "/payments")]
+pub async fn payments(event: Event) -> Result<Event, actix_web::Error> {
+    Ok(reply(event, "payment.accepted"))
+}
+
+faas_rust::faas_main!(orders, payments);
+
+// Would define a second main without no_main
+#[faas_function(no_main)]
+pub async fn standalone(event: Event) -> Result<Event, actix_web::Error> {
+    Ok(reply(event, "standalone.accepted"))
+}
+
+/// Sends an event to `path`, returning the head of the response in lowercase.
+fn send(port: u16, path: &str) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\ncontent-length: 0\r\n\
+         ce-id: 1\r\nce-source: /shop\r\nce-type: dev.knative.order\r\nce-specversion: 1.0\r\n\r\n",
+        path
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response.to_lowercase())
+}
+
+#[actix_rt::test]
+async fn test_no_main_function_in_app() {
+    assert_eq!(standalone::PATH, "/*");
+    let mut app = test::init_service(App::new().configure(standalone::configure)).await;
+
+    let req = TestRequest::post()
+        .uri("/any/path")
+        .header("ce-id", "1")
+        .header("ce-source", "/shop")
+        .header("ce-type", "dev.knative.order")
+        .header("ce-specversion", "1.0")
+        .to_request();
+    let res = test::call_service(&mut app, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers().get("ce-type").unwrap(), "standalone.accepted");
+}
+
+#[test]
+fn test_faas_main_mounts_every_function() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .unwrap()
+        .port();
+    std::env::set_var("PORT", port.to_string());
+    let server = std::thread::spawn(main);
+
+    let mut orders = send(port, orders::PATH);
+    while orders.is_err() {
+        std::thread::sleep(Duration::from_millis(10));
+        orders = send(port, orders::PATH);
+    }
+    let orders = orders.unwrap();
+    assert!(orders.starts_with("http/1.1 200"), "{}", orders);
+    assert!(orders.contains("ce-type: order.accepted"), "{}", orders);
+
+    let payments = send(port, payments::PATH).unwrap();
+    assert!(payments.starts_with("http/1.1 200"), "{}", payments);
+    assert!(
+        payments.contains("ce-type: payment.accepted"),
+        "{}",
+        payments
+    );
+
+    let refunds = send(port, "/refunds").unwrap();
+    assert!(refunds.starts_with("http/1.1 404"), "{}", refunds);
+
+    unsafe { libc::kill(libc::getpid(), libc::SIGTERM) };
+    server.join().unwrap().unwrap();
+}
@@ -0,0 +1,113 @@
+//! Runs the runtime in its own thread, stopping it with SIGTERM. The signal
+//! stops every runtime of the process, so this file holds a single test.
+#![cfg(unix)]
+
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::HttpResponse;
+use faas_rust::{Middleware, Runtime};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Records the middlewares and hooks as they run.
+#[derive(Clone, Default)]
+struct Journal(Arc<Mutex<Vec<String>>>);
+
+impl Journal {
+    fn record<S: Into<String>>(&self, entry: S) {
+        self.0.lock().unwrap().push(entry.into());
+    }
+
+    fn entries(&self) -> Vec<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+struct Recorder(&'static str, Journal);
+
+impl Middleware for Recorder {
+    fn on_request(&self, req: &ServiceRequest) -> Result<(), actix_web::Error> {
+        // Skips the readiness probes
+        if req.path() == "/" {
+            self.1.record(format!("{} request", self.0));
+        }
+        Ok(())
+    }
+
+    fn on_response(&self, res: &mut ServiceResponse) {
+        if res.request().path() == "/" {
+            self.1.record(format!("{} response", self.0));
+        }
+    }
+}
+
+/// Sends a request to `path`, returning the status line of the response.
+fn get(port: u16, path: &str) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n",
+        path
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response.lines().next().unwrap_or_default().to_string())
+}
+
+#[test]
+fn test_middlewares_and_hooks_run_in_order() {
+    let journal = Journal::default();
+    let port = TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .unwrap()
+        .port();
+
+    let function_journal = journal.clone();
+    let init_journal = journal.clone();
+    let shutdown_journal = journal.clone();
+    let runtime = Runtime::new()
+        .bind(([127, 0, 0, 1], port).into())
+        .workers(1)
+        .shutdown_timeout(5)
+        .wrap(Recorder("first", journal.clone()))
+        .wrap(Recorder("second", journal.clone()))
+        .route("/", move |route| {
+            let journal = function_journal.clone();
+            route.to(move || {
+                journal.record("function");
+                HttpResponse::Ok().finish()
+            })
+        })
+        .on_init(move || {
+            let journal = init_journal.clone();
+            async move { journal.record("init") }
+        })
+        .on_shutdown(move || {
+            let journal = shutdown_journal.clone();
+            async move { journal.record("shutdown") }
+        });
+    let server =
+        std::thread::spawn(move || actix_rt::System::new("runtime").block_on(runtime.run()));
+
+    while get(port, "/health/readiness").ok().as_deref() != Some("HTTP/1.1 200 OK") {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(get(port, "/").unwrap(), "HTTP/1.1 200 OK");
+
+    unsafe { libc::kill(libc::getpid(), libc::SIGTERM) };
+    server.join().unwrap().unwrap();
+
+    assert_eq!(
+        journal.entries(),
+        vec![
+            "init",
+            "first request",
+            "second request",
+            "function",
+            "first response",
+            "second response",
+            "shutdown",
+        ]
+    );
+}
@@ -0,0 +1,57 @@
+//! Stops the runtime with SIGTERM while an invocation is in flight. The
+//! signal stops every runtime of the process, so this file holds a single
+//! test.
+#![cfg(unix)]
+
+use actix_web::HttpResponse;
+use faas_rust::Runtime;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+async fn respond_slowly() -> HttpResponse {
+    actix_rt::time::delay_for(Duration::from_millis(300)).await;
+    HttpResponse::Ok().finish()
+}
+
+/// Sends a request to `path`, returning the status line of the response.
+fn get(port: u16, path: &str) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n",
+        path
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response.lines().next().unwrap_or_default().to_string())
+}
+
+#[test]
+fn test_in_flight_invocations_complete_on_sigterm() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .unwrap()
+        .port();
+
+    let runtime = Runtime::new()
+        .bind(([127, 0, 0, 1], port).into())
+        .workers(1)
+        .shutdown_timeout(5)
+        .route("/", |route| route.to(respond_slowly));
+    let server =
+        std::thread::spawn(move || actix_rt::System::new("runtime").block_on(runtime.run()));
+
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let stop = std::thread::spawn(|| {
+        // The invocation is in flight when the signal is received
+        std::thread::sleep(Duration::from_millis(100));
+        unsafe { libc::kill(libc::getpid(), libc::SIGTERM) };
+    });
+    assert_eq!(get(port, "/").unwrap(), "HTTP/1.1 200 OK");
+
+    stop.join().unwrap();
+    server.join().unwrap().unwrap();
+}
@@ -4,38 +4,143 @@ use proc_macro2::TokenStream;
 use quote::{format_ident, quote, quote_spanned};
 use std::borrow::Borrow;
 use std::error::Error;
-use syn::{spanned::Spanned, FnArg, GenericArgument, Ident, Path, PathArguments, ReturnType, Type};
+use syn::{
+    spanned::Spanned, FnArg, GenericArgument, Ident, Lit, Meta, NestedMeta, Path, PathArguments,
+    ReturnType, Type,
+};
 
 #[proc_macro_attribute]
 pub fn faas_function(
-    _args: proc_macro::TokenStream,
+    args: proc_macro::TokenStream,
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
     let function_ast: syn::ItemFn = syn::parse(item.clone()).unwrap();
+    let args = syn::parse_macro_input!(args as syn::AttributeArgs);
 
+    let function_args = match parse_function_args(args) {
+        Ok(function_args) => function_args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let user_function_name = function_ast.sig.ident.clone();
     let user_function: TokenStream = item.into();
-    let main_fn: TokenStream = quote! {
-        #[actix_rt::main]
-        async fn main() -> std::io::Result<()> {
-            faas_rust::start_runtime(|r| r.to(handle_event)).await
+    let handler = generate_handler(function_ast, function_args.encoding);
+
+    // When the function is mounted on a specific path, the main is generated
+    // by faas_rust::faas_main! together with all the other functions.
+    // With no_main the user mounts the function in its own App through
+    // the generated configure
+    let main_fn: TokenStream = if function_args.path.is_none() && !function_args.no_main {
+        quote! {
+            #[actix_rt::main]
+            async fn main() -> std::io::Result<()> {
+                faas_rust::start_runtime(#user_function_name::route).await
+            }
         }
+    } else {
+        quote! {}
     };
-    let handler = generate_handler(function_ast);
+    let path = function_args.path.unwrap_or_else(|| String::from("/*"));
 
     let out = quote! {
-        use std::iter::FromIterator;
-
         #user_function
-        #handler
+
+        pub mod #user_function_name {
+            use std::iter::FromIterator;
+
+            pub const PATH: &str = #path;
+
+            #handler
+
+            pub fn route(r: actix_web::Route) -> actix_web::Route {
+                r.to(handle_event)
+            }
+
+            pub fn configure(cfg: &mut actix_web::web::ServiceConfig) {
+                cfg.route(PATH, route(faas_rust::function_route()));
+            }
+        }
+
         #main_fn
     };
 
     out.into()
 }
 
-fn generate_handler(function_ast: syn::ItemFn) -> TokenStream {
+struct FunctionArgs {
+    path: Option<String>,
+    no_main: bool,
+    /// Variant of `ResponseEncoding`
+    encoding: Option<Ident>,
+}
+
+fn parse_function_args(args: syn::AttributeArgs) -> Result<FunctionArgs, syn::Error> {
+    let mut function_args = FunctionArgs {
+        path: None,
+        no_main: false,
+        encoding: None,
+    };
+
+    for arg in args {
+        match &arg {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("path") => match &nv.lit {
+                Lit::Str(s) if s.value().starts_with('/') => function_args.path = Some(s.value()),
+                lit => {
+                    return Err(syn::Error::new_spanned(
+                        lit,
+                        "path should be a string starting with /",
+                    ))
+                }
+            },
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("encoding") => {
+                let variant = match &nv.lit {
+                    Lit::Str(s) => match s.value().as_str() {
+                        "binary" => Some("Binary"),
+                        "structured" => Some("Structured"),
+                        "batch" => Some("Batch"),
+                        "protobuf" => Some("Protobuf"),
+                        "protobuf-batch" => Some("ProtobufBatch"),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match variant {
+                    Some(variant) => {
+                        function_args.encoding = Some(Ident::new(variant, nv.lit.span()))
+                    }
+                    None => return Err(syn::Error::new_spanned(
+                        &nv.lit,
+                        "encoding should be one of binary, structured, batch, protobuf or protobuf-batch",
+                    )),
+                }
+            }
+            NestedMeta::Meta(Meta::Path(p)) if p.is_ident("no_main") => {
+                function_args.no_main = true
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    arg,
+                    "Unknown argument, expecting path = \"/...\", encoding = \"...\" or no_main",
+                ))
+            }
+        }
+    }
+
+    Ok(function_args)
+}
+
+fn generate_handler(function_ast: syn::ItemFn, encoding: Option<Ident>) -> TokenStream {
     let user_function_name = function_ast.sig.ident.clone();
 
+    // The response encoding of the function overrides the one of the runtime
+    let set_encoding = match encoding {
+        Some(variant) => quote! {
+            actix_web::HttpMessage::extensions_mut(&req)
+                .insert(faas_rust::response_writer::ResponseEncoding::#variant);
+        },
+        None => quote! {},
+    };
+
     // Function input
 
     let input_extracted: Vec<(Ident, TokenStream)> = function_ast.sig.inputs
@@ -54,13 +159,34 @@ fn generate_handler(function_ast: syn::ItemFn) -> TokenStream {
                         Some((varname.clone(), quote_spanned! {arg.span()=>
                             let #varname: Option<cloudevent::Event> = events.pop();
                         }))
+                    } else if is_type(ty, "Logger") {
+                        Some((varname.clone(), quote_spanned! {arg.span()=>
+                            let #varname = faas_rust::Logger::current();
+                        }))
+                    } else if is_type(ty, "Emitter") {
+                        Some((varname.clone(), quote_spanned! {arg.span()=>
+                            let #varname = faas_rust::Emitter::from_request(&req);
+                        }))
+                    } else if is_type(ty, "Context") {
+                        Some((varname.clone(), quote_spanned! {arg.span()=>
+                            let #varname: faas_rust::Context = req.extensions().get::<faas_rust::Context>().cloned().unwrap_or_default();
+                        }))
+                    } else if is_runtime_config_ref(ty) {
+                        let data_varname = format_ident!("_data{}", i);
+                        Some((varname.clone(), quote_spanned! {arg.span()=>
+                            let #data_varname = req
+                                .app_data::<actix_web::web::Data<faas_rust::RuntimeConfig>>()
+                                .cloned()
+                                .ok_or(actix_web::error::ErrorInternalServerError("Runtime config not registered"))?;
+                            let #varname: &faas_rust::RuntimeConfig = #data_varname.get_ref();
+                        }))
                     } else {
                         None
                     }
                 })
                 .unwrap_or((
                     format_ident!("{}", "err"),
-                    syn::Error::new_spanned(arg, "Type should be Event or Option<Event>").to_compile_error()
+                    syn::Error::new_spanned(arg, "Type should be Event, Option<Event>, &RuntimeConfig, Logger, Context or Emitter").to_compile_error()
                 ))
 
         )
@@ -72,7 +198,7 @@ fn generate_handler(function_ast: syn::ItemFn) -> TokenStream {
     // Function invocation
 
     let mut user_function_invocation = quote! {
-            #user_function_name(#(#input_extracted_ident),*)
+            super::#user_function_name(#(#input_extracted_ident),*)
     };
 
     if function_ast.sig.asyncness.is_some() {
@@ -94,25 +220,29 @@ fn generate_handler(function_ast: syn::ItemFn) -> TokenStream {
     // fn handleEvent()
 
     let out = quote! {
-            async fn handle_event(
+        #[allow(unused_variables)]
+        pub async fn handle_event(
             req: actix_web::HttpRequest,
-            body: actix_web::web::Bytes,
+            payload: actix_web::web::Payload,
         ) -> Result<actix_web::HttpResponse, actix_web::Error> {
-            let value = faas_rust::request_reader::read_cloud_event(req, body).await?;
-
-            // Unzip
-            let (encoding, mut events) = match value {
-                Some((encoding, events)) => (Some(encoding), events),
-                None => (None, vec![])
-            };
-
-            events.reverse();
-
-            #(#input_extracted_stmts)*
-
-            let output = #user_function_invocation?;
-            let mapped_output: Vec<cloudevent::Event> = #output_mapper;
-            faas_rust::response_writer::write_cloud_event(mapped_output, encoding)
+            #set_encoding
+            faas_rust::invocation::handle(req, payload, |req, mut events| async move {
+                events.reverse();
+
+                #(#input_extracted_stmts)*
+
+                let output = match #user_function_invocation {
+                    Ok(output) => output,
+                    Err(e) => {
+                        #[allow(unused_imports)]
+                        use faas_rust::error::dispatch::{FromActixError, FromFunctionError, FromStdError};
+                        return Err((&&&faas_rust::error::dispatch::ErrorWrapper::new(e)).into_runtime_error());
+                    }
+                };
+                let mapped_output: Vec<cloudevent::Event> = #output_mapper;
+                Ok(mapped_output)
+            })
+            .await
         }
     };
 
@@ -166,6 +296,25 @@ fn is_event(ty: &Type) -> bool {
     }
 }
 
+fn is_type(ty: &Type, name: &str) -> bool {
+    match ty {
+        Type::Path(type_path) => type_path.path.segments.last().unwrap().ident == name,
+        _ => false,
+    }
+}
+
+fn is_runtime_config_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(type_ref) => match &*type_ref.elem {
+            Type::Path(type_path) => {
+                type_path.path.segments.last().unwrap().ident == "RuntimeConfig"
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
 fn extract_type_from_vec(ty: &Type) -> Option<&Type> {
     fn path_is_vec(path: &Path) -> bool {
         path.leading_colon.is_none()